@@ -0,0 +1,181 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::len_zero)]
+extern crate ce;
+extern crate chess;
+extern crate num_cpus;
+use ce::eval;
+use std::fs;
+use std::str::FromStr;
+use std::thread;
+
+// texel tuning: nudges one weight at a time and keeps the change whenever the
+// mean squared error between the game results and sigmoid(eval) goes down.
+// the dataset should hold quiet positions, as the plain static eval is fitted.
+
+// white's expected score, or a white relative centipawn score that gets turned into one with k.
+#[derive(Copy, Clone)]
+enum Label {
+  Result(f64),
+  Centipawns(i32),
+}
+
+struct Entry {
+  board: chess::Board,
+  label: Label,
+}
+
+fn parse_label(rest: &[&str]) -> Option<Label> {
+  for (i, token) in rest.iter().enumerate() {
+    let t = token.trim_matches(|c| c == '"' || c == ';' || c == '[' || c == ']' || c == ',');
+    match t {
+      "1-0"     => return Some(Label::Result(1.0)),
+      "0-1"     => return Some(Label::Result(0.0)),
+      "1/2-1/2" => return Some(Label::Result(0.5)),
+      "cp" | "ce" => return rest.get(i + 1).and_then(|v| v.trim_matches(';').parse().ok()).map(Label::Centipawns),
+      _ => {},
+    }
+    if let Ok(r) = t.parse::<f64>() {
+      if t.contains('.') && (0.0..=1.0).contains(&r) {
+        return Some(Label::Result(r));
+      }
+    }
+  }
+  return None;
+}
+
+// one position per line: an epd or fen followed by a result like 1-0, "0-1", [0.5], or cp 35.
+fn load_dataset(path: &str) -> Result<Vec<Entry>, String> {
+  let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+  let mut entries = vec![];
+  for (n, line) in text.lines().enumerate() {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 5 {
+      continue;
+    }
+    let board = chess::Board::from_str(tokens[..4].join(" ").trim_end_matches(';'));
+    // a full fen carries the move counters before the result.
+    let counters = tokens.len() > 6 && tokens[4].parse::<u32>().is_ok() && tokens[5].parse::<u32>().is_ok();
+    let label = parse_label(&tokens[if counters {6} else {4}..]);
+    match (board, label) {
+      (Ok(b), Some(l)) => entries.push(Entry { board: b, label: l }),
+      _                => println!("skipping line {}: {}", n + 1, line),
+    }
+  }
+  return Ok(entries);
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+  return 1.0 / (1.0 + 10f64.powf(-k * score / 400.0));
+}
+
+fn error(entries: &[Entry], weights: &eval::Weights, k: f64, threads: usize) -> f64 {
+  let chunk = entries.len().div_ceil(threads).max(1);
+  let total: f64 = thread::scope(|s| {
+    let handles: Vec<_> = entries.chunks(chunk).map(|part| {
+      s.spawn(move || {
+        let mut sum = 0.0;
+        for e in part {
+          let target = match e.label {
+            Label::Result(r)     => r,
+            Label::Centipawns(c) => sigmoid(c as f64, k),
+          };
+          let diff = target - sigmoid(eval::evaluate_uncached(&e.board, weights) as f64, k);
+          sum += diff * diff;
+        }
+        return sum;
+      })
+    }).collect();
+    handles.into_iter().map(|h| h.join().unwrap()).sum()
+  });
+  return total / entries.len() as f64;
+}
+
+// the scaling constant that best fits the current weights, found by narrowing a grid search.
+fn find_k(entries: &[Entry], weights: &eval::Weights, threads: usize) -> f64 {
+  let mut best = (1.0, error(entries, weights, 1.0, threads));
+  let mut step = 0.5;
+  for _ in 0..6 {
+    let center = best.0;
+    for i in -4..5 {
+      let k = center + i as f64 * step;
+      if k <= 0.0 {
+        continue;
+      }
+      let e = error(entries, weights, k, threads);
+      if e < best.1 {
+        best = (k, e);
+      }
+    }
+    step /= 4.0;
+  }
+  return best.0;
+}
+
+fn get(weights: &mut eval::Weights, i: usize) -> i32 {
+  return *weights.params()[i].1;
+}
+
+fn set(weights: &mut eval::Weights, i: usize, value: i32) {
+  *weights.params().swap_remove(i).1 = value;
+}
+
+// usage: tune <dataset> <output> [passes] [start weights]
+fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if args.len() < 2 {
+    println!("usage: tune <dataset> <output> [passes] [start weights]");
+    return;
+  }
+  let passes: usize = args.get(2).and_then(|p| p.parse().ok()).unwrap_or(10);
+  let mut weights = match args.get(3) {
+    Some(path) => match eval::Weights::load(path) {
+      Ok(w)  => w,
+      Err(e) => {
+        println!("{}", e);
+        return;
+      },
+    },
+    None => eval::Weights::default(),
+  };
+  let entries = match load_dataset(&args[0]) {
+    Ok(e) if e.len() > 0 => e,
+    Ok(_)  => {
+      println!("no positions in {}", args[0]);
+      return;
+    },
+    Err(e) => {
+      println!("{}", e);
+      return;
+    },
+  };
+
+  let threads = num_cpus::get();
+  let k = find_k(&entries, &weights, threads);
+  let count = weights.params().len();
+  let mut best = error(&entries, &weights, k, threads);
+  println!("{} positions, {} parameters, k {:.4}, error {:.6}", entries.len(), count, k, best);
+
+  for pass in 1..passes + 1 {
+    let mut improved = 0;
+    for i in 0..count {
+      let value = get(&mut weights, i);
+      for delta in [1, -1].iter() {
+        set(&mut weights, i, value + delta);
+        let e = error(&entries, &weights, k, threads);
+        if e < best {
+          best = e;
+          improved += 1;
+          break;
+        }
+        set(&mut weights, i, value);
+      }
+    }
+    println!("pass {} error {:.6} improved {}", pass, best, improved);
+    if let Err(e) = weights.save(&args[1]) {
+      println!("{}", e);
+      return;
+    }
+    if improved == 0 {
+      break;
+    }
+  }
+}
@@ -0,0 +1,280 @@
+extern crate chess;
+extern crate shakmaty;
+use self::shakmaty::zobrist::{Zobrist64, ZobristHash};
+use self::shakmaty::EnPassantMode;
+use std::collections::HashMap;
+use std::fs;
+use std::time::SystemTime;
+use syzygy;
+
+pub static DEFAULT_BOOK_DEPTH: usize = 16;
+// key 8 bytes | move 2 | weight 2 | learn 4, all big endian.
+static ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone)]
+struct BookEntry {
+  key:    u64,
+  mv:     u16,
+  weight: u16,
+}
+
+// a polyglot book, kept in memory and sorted by key like the file itself.
+pub struct Book {
+  entries: Vec<BookEntry>,
+  seed:    u64,
+}
+
+// polyglot uses its own zobrist keys, which shakmaty implements.
+pub fn polyglot_key(board: &chess::Board) -> u64 {
+  return match syzygy::to_position(board) {
+    Some(pos) => pos.zobrist_hash::<Zobrist64>(EnPassantMode::Legal).0,
+    None      => 0,
+  };
+}
+
+// to file 3 bits | to rank 3 | from file 3 | from rank 3 | promotion 3, castling as king takes rook.
+fn encode_move(board: &chess::Board, m: chess::ChessMove) -> u16 {
+  let from = m.get_source();
+  let mut to = m.get_dest();
+  let king_move = board.piece_on(from) == Some(chess::Piece::King);
+  let file_delta = to.get_file().to_index() as i32 - from.get_file().to_index() as i32;
+  if king_move && file_delta.abs() == 2 {
+    let rook_file = if file_delta > 0 {chess::File::H} else {chess::File::A};
+    to = chess::Square::make_square(from.get_rank(), rook_file);
+  }
+  let promotion: u16 = match m.get_promotion() {
+    Some(chess::Piece::Knight) => 1,
+    Some(chess::Piece::Bishop) => 2,
+    Some(chess::Piece::Rook)   => 3,
+    Some(chess::Piece::Queen)  => 4,
+    _                          => 0,
+  };
+  return to.get_file().to_index() as u16
+    | (to.get_rank().to_index() as u16) << 3
+    | (from.get_file().to_index() as u16) << 6
+    | (from.get_rank().to_index() as u16) << 9
+    | promotion << 12;
+}
+
+fn decode_move(board: &chess::Board, mv: u16) -> Option<chess::ChessMove> {
+  let square = |file: u16, rank: u16| chess::Square::make_square(chess::Rank::from_index(rank as usize), chess::File::from_index(file as usize));
+  let from = square((mv >> 6) & 7, (mv >> 9) & 7);
+  let mut to = square(mv & 7, (mv >> 3) & 7);
+  let promotion = match (mv >> 12) & 7 {
+    1 => Some(chess::Piece::Knight),
+    2 => Some(chess::Piece::Bishop),
+    3 => Some(chess::Piece::Rook),
+    4 => Some(chess::Piece::Queen),
+    _ => None,
+  };
+  if board.piece_on(from) == Some(chess::Piece::King) && board.color_on(to) == board.color_on(from) {
+    let king_file = if to.get_file() == chess::File::H {chess::File::G} else {chess::File::C};
+    to = chess::Square::make_square(from.get_rank(), king_file);
+  }
+  let m = chess::ChessMove::new(from, to, promotion);
+  return if board.legal(m) {Some(m)} else {None};
+}
+
+impl Book {
+  pub fn open(path: &str) -> Result<Self, String> {
+    let data = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    if data.len() % ENTRY_SIZE != 0 {
+      return Err(format!("{} is not a polyglot book", path));
+    }
+    let entries = data.chunks(ENTRY_SIZE).map(|c| BookEntry {
+      key:    u64::from_be_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]),
+      mv:     u16::from_be_bytes([c[8], c[9]]),
+      weight: u16::from_be_bytes([c[10], c[11]]),
+    }).collect();
+    let seed = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64) | 1;
+    return Ok(Self { entries: entries, seed: seed });
+  }
+
+  pub fn size(&self) -> usize {
+    return self.entries.len();
+  }
+
+  // the legal book moves of the position with their weights.
+  pub fn moves(&self, board: &chess::Board) -> Vec<(chess::ChessMove, u16)> {
+    let key = polyglot_key(board);
+    let first = self.entries.partition_point(|e| e.key < key);
+    let mut moves = vec![];
+    for e in self.entries[first..].iter().take_while(|e| e.key == key) {
+      if let Some(m) = decode_move(board, e.mv) {
+        moves.push((m, e.weight));
+      }
+    }
+    return moves;
+  }
+
+  // the heaviest move, or a random one with chances proportional to the weights.
+  pub fn pick(&mut self, board: &chess::Board, best: bool) -> Option<chess::ChessMove> {
+    let moves = self.moves(board);
+    if best {
+      return moves.iter().max_by_key(|m| m.1).map(|m| m.0);
+    }
+    let total: u64 = moves.iter().map(|m| m.1 as u64).sum();
+    if total == 0 {
+      return moves.first().map(|m| m.0);
+    }
+    // xorshift64
+    self.seed ^= self.seed << 13;
+    self.seed ^= self.seed >> 7;
+    self.seed ^= self.seed << 17;
+    let mut r = self.seed % total;
+    for (m, weight) in moves {
+      if r < weight as u64 {
+        return Some(m);
+      }
+      r -= weight as u64;
+    }
+    return None;
+  }
+}
+
+// the san moves of every game in a pgn, with white's result.
+fn parse_pgn(text: &str) -> Vec<(Vec<String>, f32)> {
+  let mut games = vec![];
+  let mut moves: Vec<String> = vec![];
+  let mut result: Option<f32> = None;
+  let mut depth = 0;
+  let mut in_comment = false;
+  for line in text.lines() {
+    let line = line.trim();
+    if !in_comment && depth == 0 && line.starts_with('[') {
+      if moves.len() > 0 {
+        if let Some(r) = result {
+          games.push((moves.clone(), r));
+        }
+        moves.clear();
+        result = None;
+      }
+      if line.starts_with("[Result ") {
+        result = match line.trim_start_matches("[Result ").trim_end_matches(']').trim_matches('"') {
+          "1-0"     => Some(1.0),
+          "0-1"     => Some(0.0),
+          "1/2-1/2" => Some(0.5),
+          _         => None,
+        };
+      }
+      continue;
+    }
+
+    // drop comments, variations and rest of line comments.
+    let mut clean = String::new();
+    for c in line.chars() {
+      match c {
+        '{' if depth == 0 => in_comment = true,
+        '}' if in_comment => in_comment = false,
+        ';' if !in_comment && depth == 0 => break,
+        '(' if !in_comment => depth += 1,
+        ')' if !in_comment && depth > 0 => depth -= 1,
+        _ if !in_comment && depth == 0 => clean.push(c),
+        _ => {},
+      }
+    }
+    for token in clean.split_whitespace() {
+      let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+      let san = san.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+      if san.is_empty() || san.starts_with('$') || san == "*" || san.contains('-') && !san.starts_with('O') {
+        continue;
+      }
+      moves.push(san);
+    }
+  }
+  if moves.len() > 0 {
+    if let Some(r) = result {
+      games.push((moves, r));
+    }
+  }
+  return games;
+}
+
+// builds a book out of the first plies of every finished game, weighting each move by the
+// points its side scored with it: 2 for a win, 1 for a draw.
+pub fn build(pgn: &str, output: &str, plies: usize) -> Result<usize, String> {
+  let text = fs::read_to_string(pgn).map_err(|e| format!("cannot read {}: {}", pgn, e))?;
+  let mut counts: HashMap<(u64, u16), u64> = HashMap::new();
+  for (moves, result) in parse_pgn(&text) {
+    let mut board = chess::Board::default();
+    for san in moves.iter().take(plies) {
+      let m = match chess::ChessMove::from_san(&board, san) {
+        Ok(m)  => m,
+        Err(_) => break,
+      };
+      let score = if board.side_to_move() == chess::Color::White {result} else {1.0 - result};
+      *counts.entry((polyglot_key(&board), encode_move(&board, m))).or_insert(0) += (score * 2.0) as u64;
+      board = board.make_move_new(m);
+    }
+  }
+
+  let max = counts.values().copied().max().unwrap_or(0).max(1);
+  let mut entries: Vec<BookEntry> = counts.into_iter().map(|((key, mv), count)| BookEntry {
+    key:    key,
+    mv:     mv,
+    weight: if max > u16::MAX as u64 {(count * u16::MAX as u64 / max) as u16} else {count as u16},
+  }).filter(|e| e.weight > 0).collect();
+  entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+
+  let mut data = Vec::with_capacity(entries.len() * ENTRY_SIZE);
+  for e in entries.iter() {
+    data.extend_from_slice(&e.key.to_be_bytes());
+    data.extend_from_slice(&e.mv.to_be_bytes());
+    data.extend_from_slice(&e.weight.to_be_bytes());
+    data.extend_from_slice(&0u32.to_be_bytes());
+  }
+  fs::write(output, data).map_err(|e| format!("cannot write {}: {}", output, e))?;
+  return Ok(entries.len());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  #[test]
+  fn keys() {
+    assert_eq!(polyglot_key(&chess::Board::default()), 0x463b96181691fc9c);
+    let board = chess::Board::from_str("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+    assert_eq!(polyglot_key(&board), 0x22a48b5a8e47ff78);
+  }
+
+  #[test]
+  fn castling_moves() {
+    let board = chess::Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    let short = chess::ChessMove::new(chess::Square::E1, chess::Square::G1, None);
+    let long = chess::ChessMove::new(chess::Square::E1, chess::Square::C1, None);
+    // e1h1 and e1a1 in polyglot.
+    assert_eq!(encode_move(&board, short), 4 << 6 | 7);
+    assert_eq!(encode_move(&board, long), 4 << 6);
+    assert_eq!(decode_move(&board, encode_move(&board, short)), Some(short));
+    assert_eq!(decode_move(&board, encode_move(&board, long)), Some(long));
+  }
+  #[test]
+  fn build_from_pgn() {
+    let pgn = "[Event \"a\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n\
+               [Event \"b\"]\n[Result \"1/2-1/2\"]\n\n1. e4 {a comment} c5 (1... e5 2. Nf3) 2. Nf3 1/2-1/2\n\n\
+               [Event \"c\"]\n[Result \"0-1\"]\n\n1. d4 d5 2. c4 0-1\n\n\
+               [Event \"d\"]\n[Result \"*\"]\n\n1. c4 e5 *\n";
+    let dir = std::env::temp_dir();
+    let input = dir.join(format!("book-test-{}.pgn", std::process::id()));
+    let output = dir.join(format!("book-test-{}.bin", std::process::id()));
+    fs::write(&input, pgn).unwrap();
+    let written = build(input.to_str().unwrap(), output.to_str().unwrap(), 2);
+    let book = Book::open(output.to_str().unwrap());
+    let _ = fs::remove_file(&input);
+    let _ = fs::remove_file(&output);
+    // e4 scored a win and a draw, c5 and d5 a draw and a win for black, lost moves and unfinished games count nothing.
+    assert_eq!(written, Ok(3));
+    let book = book.unwrap();
+    let start = chess::Board::default();
+    assert_eq!(book.moves(&start), vec![(chess::ChessMove::from_str("e2e4").unwrap(), 3)]);
+    let e4 = start.make_move_new(chess::ChessMove::from_str("e2e4").unwrap());
+    assert_eq!(book.moves(&e4), vec![(chess::ChessMove::from_str("c7c5").unwrap(), 1)]);
+    let d4 = start.make_move_new(chess::ChessMove::from_str("d2d4").unwrap());
+    assert_eq!(book.moves(&d4), vec![(chess::ChessMove::from_str("d7d5").unwrap(), 2)]);
+    // only the first two plies of each game go into the book.
+    let e5 = e4.make_move_new(chess::ChessMove::from_str("e7e5").unwrap());
+    assert!(book.moves(&e5).is_empty());
+  }
+}
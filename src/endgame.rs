@@ -0,0 +1,289 @@
+extern crate chess;
+use std::sync::OnceLock;
+
+// scale factors are out of SCALE_NORMAL and only ever shrink the endgame half of the score.
+pub static SCALE_NORMAL: i32 = 64;
+// a won ending, far from mate scores but above anything the normal evaluation returns.
+pub static KNOWN_WIN: i32 = 10000;
+
+// non pawn material in pawn units, for the drawish material checks.
+static MINOR: u32 = 3;
+static ROOK: u32 = 5;
+static QUEEN: u32 = 9;
+
+// one decimal digit per piece count: pawns, knights, bishops, rooks, queens.
+const fn key(pawns: u32, knights: u32, bishops: u32, rooks: u32, queens: u32) -> u32 {
+  return pawns + 10 * knights + 100 * bishops + 1000 * rooks + 10000 * queens;
+}
+
+static BARE: u32 = key(0, 0, 0, 0, 0);
+static KP: u32 = key(1, 0, 0, 0, 0);
+static KN: u32 = key(0, 1, 0, 0, 0);
+static KB: u32 = key(0, 0, 1, 0, 0);
+static KNN: u32 = key(0, 2, 0, 0, 0);
+static KBN: u32 = key(0, 1, 1, 0, 0);
+
+fn count(board: &chess::Board, piece: chess::Piece, color: chess::Color) -> u32 {
+  return (*board.pieces(piece) & *board.color_combined(color)).popcnt();
+}
+
+// the material signature of one side.
+pub fn signature(board: &chess::Board, color: chess::Color) -> u32 {
+  return key(
+    count(board, chess::Piece::Pawn, color),
+    count(board, chess::Piece::Knight, color),
+    count(board, chess::Piece::Bishop, color),
+    count(board, chess::Piece::Rook, color),
+    count(board, chess::Piece::Queen, color),
+  );
+}
+
+fn non_pawn_material(board: &chess::Board, color: chess::Color) -> u32 {
+  return MINOR * (count(board, chess::Piece::Knight, color) + count(board, chess::Piece::Bishop, color))
+    + ROOK * count(board, chess::Piece::Rook, color)
+    + QUEEN * count(board, chess::Piece::Queen, color);
+}
+
+fn distance(a: chess::Square, b: chess::Square) -> i32 {
+  let files = (a.get_file().to_index() as i32 - b.get_file().to_index() as i32).abs();
+  let ranks = (a.get_rank().to_index() as i32 - b.get_rank().to_index() as i32).abs();
+  return files.max(ranks);
+}
+
+// 0 in the centre up to 6 in the corners.
+fn center_distance(sq: chess::Square) -> i32 {
+  let file = sq.get_file().to_index() as i32;
+  let rank = sq.get_rank().to_index() as i32;
+  return (3 - file).max(file - 4) + (3 - rank).max(rank - 4);
+}
+
+fn dark(sq: chess::Square) -> bool {
+  return (sq.get_file().to_index() + sq.get_rank().to_index()).is_multiple_of(2);
+}
+
+// exact evaluations of the endings we know, from white's point of view.
+pub fn evaluate(board: &chess::Board) -> Option<i32> {
+  let white = signature(board, chess::Color::White);
+  let black = signature(board, chess::Color::Black);
+  let (strong, weak_key, strong_key) = if black == BARE {
+    (chess::Color::White, black, white)
+  } else {
+    (chess::Color::Black, white, black)
+  };
+  if weak_key != BARE {
+    return None;
+  }
+
+  let score = if strong_key == BARE || strong_key == KN || strong_key == KB || strong_key == KNN {
+    0
+  } else if strong_key == KP {
+    kpk(board, strong)
+  } else if strong_key == KBN {
+    kbnk(board, strong)
+  } else if count(board, chess::Piece::Queen, strong) + count(board, chess::Piece::Rook, strong) > 0 {
+    mop_up(board, strong)
+  } else {
+    return None;
+  };
+  return Some(if strong == chess::Color::White {score} else {-score});
+}
+
+// drives the lone king to the edge and brings ours closer, keeping promotions worth it.
+fn mop_up(board: &chess::Board, strong: chess::Color) -> i32 {
+  let strong_king = board.king_square(strong);
+  let weak_king = board.king_square(!strong);
+  let material = (count(board, chess::Piece::Pawn, strong) + non_pawn_material(board, strong)) as i32 * 100;
+  return KNOWN_WIN + material + 20 * center_distance(weak_king) + 10 * (7 - distance(strong_king, weak_king));
+}
+
+// the lone king can only be mated in a corner of the bishop's colour.
+fn kbnk(board: &chess::Board, strong: chess::Color) -> i32 {
+  let strong_king = board.king_square(strong);
+  let weak_king = board.king_square(!strong);
+  let bishop = (*board.pieces(chess::Piece::Bishop) & *board.color_combined(strong)).to_square();
+  let corners = if dark(bishop) {[chess::Square::A1, chess::Square::H8]} else {[chess::Square::H1, chess::Square::A8]};
+  let corner = distance(weak_king, corners[0]).min(distance(weak_king, corners[1]));
+  return KNOWN_WIN + 40 * (7 - corner) + 10 * (7 - distance(strong_king, weak_king));
+}
+
+fn kpk(board: &chess::Board, strong: chess::Color) -> i32 {
+  let pawn = (*board.pieces(chess::Piece::Pawn) & *board.color_combined(strong)).to_square();
+  // look at it as white with the pawn on the queen side.
+  let flip = if strong == chess::Color::White {0} else {56};
+  let mirror = if pawn.get_file().to_index() > 3 {7} else {0};
+  let normalize = |sq: chess::Square| sq.to_index() ^ flip ^ mirror;
+  let white_to_move = board.side_to_move() == strong;
+
+  let index = kpk_index(white_to_move, normalize(board.king_square(strong)), normalize(board.king_square(!strong)), normalize(pawn));
+  if !kpk_bitbase()[index] {
+    return 0;
+  }
+  let rank = (normalize(pawn) / 8) as i32;
+  return KNOWN_WIN + 100 + 20 * rank;
+}
+
+// how much of the endgame score the side that is ahead can hope to keep.
+pub fn scale_factor(board: &chess::Board, strong: chess::Color) -> i32 {
+  let strong_material = non_pawn_material(board, strong);
+  let weak_material = non_pawn_material(board, !strong);
+  if count(board, chess::Piece::Pawn, strong) == 0 && strong_material <= weak_material + MINOR {
+    if strong_material < ROOK {
+      return 0;
+    }
+    return if weak_material <= MINOR {4} else {14};
+  }
+
+  // opposite coloured bishops.
+  let bishops = *board.pieces(chess::Piece::Bishop);
+  let white_bishops = bishops & *board.color_combined(chess::Color::White);
+  let black_bishops = bishops & *board.color_combined(chess::Color::Black);
+  if white_bishops.popcnt() == 1 && black_bishops.popcnt() == 1 && dark(white_bishops.to_square()) != dark(black_bishops.to_square()) {
+    let only_bishops = strong_material == MINOR && weak_material == MINOR;
+    return if only_bishops {16} else {40};
+  }
+  return SCALE_NORMAL;
+}
+
+// kpk bitbase: white king, black king and a white pawn on files a-d, ranks 2-7.
+static KPK_SIZE: usize = 2 * 24 * 64 * 64;
+static UNKNOWN: u8 = 1;
+static DRAW: u8 = 2;
+static WIN: u8 = 4;
+
+fn kpk_index(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> usize {
+  let pawn_index = (pawn / 8 - 1) * 4 + pawn % 8;
+  return ((pawn_index * 2 + white_to_move as usize) * 64 + white_king) * 64 + black_king;
+}
+
+fn kpk_bitbase() -> &'static Vec<bool> {
+  static BITBASE: OnceLock<Vec<bool>> = OnceLock::new();
+  return BITBASE.get_or_init(build_kpk);
+}
+
+fn square_distance(a: usize, b: usize) -> i32 {
+  return distance(chess::ALL_SQUARES[a], chess::ALL_SQUARES[b]);
+}
+
+fn king_moves(sq: usize) -> chess::BitBoard {
+  return chess::get_king_moves(chess::ALL_SQUARES[sq]);
+}
+
+// positions the rules settle right away, everything else starts out unknown.
+fn kpk_initial(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> u8 {
+  let pawn_attacks = chess::get_pawn_attacks(chess::ALL_SQUARES[pawn], chess::Color::White, !chess::EMPTY);
+  let black_king_bb = chess::BitBoard::from_square(chess::ALL_SQUARES[black_king]);
+  if square_distance(white_king, black_king) <= 1 || white_king == pawn || black_king == pawn || (white_to_move && (pawn_attacks & black_king_bb).popcnt() > 0) {
+    return 0;
+  }
+  // the pawn promotes and the queen can't be taken.
+  let stop = pawn + 8;
+  if white_to_move && pawn / 8 == 6 && white_king != stop && (square_distance(black_king, stop) > 1 || square_distance(white_king, stop) == 1) {
+    return WIN;
+  }
+  // stalemate, or the black king takes an undefended pawn.
+  if !white_to_move {
+    let escapes = king_moves(black_king) & !(king_moves(white_king) | pawn_attacks);
+    let takes = square_distance(black_king, pawn) == 1 && square_distance(white_king, pawn) > 1;
+    if escapes.popcnt() == 0 || takes {
+      return DRAW;
+    }
+  }
+  return UNKNOWN;
+}
+
+fn build_kpk() -> Vec<bool> {
+  let mut db = vec![0u8; KPK_SIZE];
+  let mut positions = vec![];
+  for pawn_index in 0..24 {
+    let pawn = (pawn_index / 4 + 1) * 8 + pawn_index % 4;
+    for white_to_move in [false, true].iter() {
+      for white_king in 0..64 {
+        for black_king in 0..64 {
+          let i = kpk_index(*white_to_move, white_king, black_king, pawn);
+          db[i] = kpk_initial(*white_to_move, white_king, black_king, pawn);
+          if db[i] == UNKNOWN {
+            positions.push((*white_to_move, white_king, black_king, pawn));
+          }
+        }
+      }
+    }
+  }
+
+  // white wins if any move wins, black draws if any move draws, until nothing changes.
+  let mut changed = true;
+  while changed {
+    changed = false;
+    for &(white_to_move, white_king, black_king, pawn) in positions.iter() {
+      let index = kpk_index(white_to_move, white_king, black_king, pawn);
+      if db[index] != UNKNOWN {
+        continue;
+      }
+      let mut seen = 0;
+      if white_to_move {
+        for sq in king_moves(white_king) {
+          seen |= db[kpk_index(false, sq.to_index(), black_king, pawn)];
+        }
+        if pawn / 8 < 6 {
+          seen |= db[kpk_index(false, white_king, black_king, pawn + 8)];
+        }
+        if pawn / 8 == 1 && pawn + 8 != white_king && pawn + 8 != black_king {
+          seen |= db[kpk_index(false, white_king, black_king, pawn + 16)];
+        }
+      } else {
+        for sq in king_moves(black_king) {
+          seen |= db[kpk_index(true, white_king, sq.to_index(), pawn)];
+        }
+      }
+
+      let (good, bad) = if white_to_move {(WIN, DRAW)} else {(DRAW, WIN)};
+      let result = if seen & good != 0 {good} else if seen & UNKNOWN != 0 {UNKNOWN} else {bad};
+      if result != UNKNOWN {
+        db[index] = result;
+        changed = true;
+      }
+    }
+  }
+  return db.iter().map(|r| *r == WIN).collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn eval(fen: &str) -> Option<i32> {
+    return evaluate(&chess::Board::from_str(fen).unwrap());
+  }
+
+  #[test]
+  fn kpk() {
+    // the king two squares in front of its pawn always wins, a rook pawn against a king in the corner does not.
+    assert!(eval("8/8/4k3/8/4K3/8/4P3/8 b - - 0 1").unwrap() > KNOWN_WIN);
+    assert!(eval("8/8/4k3/8/8/4K3/4P3/8 w - - 0 1").unwrap() > KNOWN_WIN);
+    assert!(eval("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap() > KNOWN_WIN);
+    assert_eq!(eval("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(0));
+    assert_eq!(eval("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"), Some(0));
+    assert_eq!(eval("k7/8/K7/P7/8/8/8/8 w - - 0 1"), Some(0));
+    assert_eq!(eval("8/8/8/8/4K3/8/4p3/3k4 b - - 0 1").map(|s| s < -KNOWN_WIN), Some(true));
+    assert_eq!(eval("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1"), Some(0));
+  }
+
+  #[test]
+  fn insufficient_material() {
+    assert_eq!(eval("8/8/4k3/8/8/3NK3/4N3/8 w - - 0 1"), Some(0));
+    assert_eq!(eval("8/8/4k3/8/8/3BK3/8/8 b - - 0 1"), Some(0));
+    let board = chess::Board::from_str("8/8/4k3/2b5/8/3RK3/8/8 w - - 0 1").unwrap();
+    assert_eq!(evaluate(&board), None);
+    assert!(scale_factor(&board, chess::Color::White) < SCALE_NORMAL / 4);
+  }
+
+  #[test]
+  fn mating_material() {
+    assert!(eval("8/8/4k3/8/8/3QK3/8/8 w - - 0 1").unwrap() > KNOWN_WIN);
+    assert!(eval("8/8/4K3/8/8/3rk3/8/8 w - - 0 1").unwrap() < -KNOWN_WIN);
+    // kbnk prefers the lone king near a corner of the bishop's colour.
+    let near = eval("k7/8/1K6/8/8/8/8/5B1N w - - 0 1").unwrap();
+    let far = eval("7k/8/6K1/8/8/8/8/5B1N w - - 0 1").unwrap();
+    assert!(near > far);
+  }
+}
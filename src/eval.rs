@@ -0,0 +1,623 @@
+extern crate chess;
+use pawns;
+use endgame;
+use std::collections::HashMap;
+use std::fs;
+
+// piece values, indexed by chess::Piece::to_index (pawn, knight, bishop, rook, queen, king).
+const MG_VALUE: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+const EG_VALUE: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+// how much each piece counts towards the middlegame, a full board adds up to MAX_PHASE.
+static PHASE_INC: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub static MAX_PHASE: i32 = 24;
+
+// piece-square tables are written from white's point of view with a8 first,
+// so a white piece on sq reads index sq ^ 56 and a black one reads sq.
+const MG_PAWN: [i32; 64] = [
+    0,   0,   0,   0,   0,   0,   0,   0,
+   98, 134,  61,  95,  68, 126,  34, -11,
+   -6,   7,  26,  31,  65,  56,  25, -20,
+  -14,  13,   6,  21,  23,  12,  17, -23,
+  -27,  -2,  -5,  12,  17,   6,  10, -25,
+  -26,  -4,  -4, -10,   3,   3,  33, -12,
+  -35,  -1, -20, -23, -15,  24,  38, -22,
+    0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const EG_PAWN: [i32; 64] = [
+    0,   0,   0,   0,   0,   0,   0,   0,
+  178, 173, 158, 134, 147, 132, 165, 187,
+   94, 100,  85,  67,  56,  53,  82,  84,
+   32,  24,  13,   5,  -2,   4,  17,  17,
+   13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+    4,   7,  -6,   1,   0,  -5,  -1,  -8,
+   13,   8,   8,  10,  13,   0,   2,  -7,
+    0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const MG_KNIGHT: [i32; 64] = [
+  -167, -89, -34, -49,  61, -97, -15, -107,
+   -73, -41,  72,  36,  23,  62,   7,  -17,
+   -47,  60,  37,  65,  84, 129,  73,   44,
+    -9,  17,  19,  53,  37,  69,  18,   22,
+   -13,   4,  16,  13,  28,  19,  21,   -8,
+   -23,  -9,  12,  10,  19,  17,  25,  -16,
+   -29, -53, -12,  -3,  -1,  18, -14,  -19,
+  -105, -21, -58, -33, -17, -28, -19,  -23,
+];
+
+const EG_KNIGHT: [i32; 64] = [
+  -58, -38, -13, -28, -31, -27, -63, -99,
+  -25,  -8, -25,  -2,  -9, -25, -24, -52,
+  -24, -20,  10,   9,  -1,  -9, -19, -41,
+  -17,   3,  22,  22,  22,  11,   8, -18,
+  -18,  -6,  16,  25,  16,  17,   4, -18,
+  -23,  -3,  -1,  15,  10,  -3, -20, -22,
+  -42, -20, -10,  -5,  -2, -20, -23, -44,
+  -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+const MG_BISHOP: [i32; 64] = [
+  -29,   4, -82, -37, -25, -42,   7,  -8,
+  -26,  16, -18, -13,  30,  59,  18, -47,
+  -16,  37,  43,  40,  35,  50,  37,  -2,
+   -4,   5,  19,  50,  37,  37,   7,  -2,
+   -6,  13,  13,  26,  34,  12,  10,   4,
+    0,  15,  15,  15,  14,  27,  18,  10,
+    4,  15,  16,   0,   7,  21,  33,   1,
+  -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+const EG_BISHOP: [i32; 64] = [
+  -14, -21, -11,  -8,  -7,  -9, -17, -24,
+   -8,  -4,   7, -12,  -3, -13,  -4, -14,
+    2,  -8,   0,  -1,  -2,   6,   0,   4,
+   -3,   9,  12,   9,  14,  10,   3,   2,
+   -6,   3,  13,  19,   7,  10,  -3,  -9,
+  -12,  -3,   8,  10,  13,   3,  -7, -15,
+  -14, -18,  -7,  -1,   4,  -9, -15, -27,
+  -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+const MG_ROOK: [i32; 64] = [
+   32,  42,  32,  51,  63,   9,  31,  43,
+   27,  32,  58,  62,  80,  67,  26,  44,
+   -5,  19,  26,  36,  17,  45,  61,  16,
+  -24, -11,   7,  26,  24,  35,  -8, -20,
+  -36, -26, -12,  -1,   9,  -7,   6, -23,
+  -45, -25, -16, -17,   3,   0,  -5, -33,
+  -44, -16, -20,  -9,  -1,  11,  -6, -71,
+  -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+const EG_ROOK: [i32; 64] = [
+   13,  10,  18,  15,  12,  12,   8,   5,
+   11,  13,  13,  11,  -3,   3,   8,   3,
+    7,   7,   7,   5,   4,  -3,  -5,  -3,
+    4,   3,  13,   1,   2,   1,  -1,   2,
+    3,   5,   8,   4,  -5,  -6,  -8, -11,
+   -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+   -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+   -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+const MG_QUEEN: [i32; 64] = [
+  -28,   0,  29,  12,  59,  44,  43,  45,
+  -24, -39,  -5,   1, -16,  57,  28,  54,
+  -13, -17,   7,   8,  29,  56,  47,  57,
+  -27, -27, -16, -16,  -1,  17,  -2,   1,
+   -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+  -14,   2, -11,  -2,  -5,   2,  14,   5,
+  -35,  -8,  11,   2,   8,  15,  -3,   1,
+   -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+const EG_QUEEN: [i32; 64] = [
+   -9,  22,  22,  27,  27,  19,  10,  20,
+  -17,  20,  32,  41,  58,  25,  30,   0,
+  -20,   6,   9,  49,  47,  35,  19,   9,
+    3,  22,  24,  45,  57,  40,  57,  36,
+  -18,  28,  19,  47,  31,  34,  39,  23,
+  -16, -27,  15,   6,   9,  17,  10,   5,
+  -22, -23, -30, -16, -16, -23, -36, -32,
+  -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+const MG_KING: [i32; 64] = [
+  -65,  23,  16, -15, -56, -34,   2,  13,
+   29,  -1, -20,  -7,  -8,  -4, -38, -29,
+   -9,  24,   2, -16, -20,   6,  22, -22,
+  -17, -20, -12, -27, -30, -25, -14, -36,
+  -49,  -1, -27, -39, -46, -44, -33, -51,
+  -14, -14, -22, -46, -44, -30, -15, -27,
+    1,   7,  -8, -64, -43, -16,   9,   8,
+  -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+const EG_KING: [i32; 64] = [
+  -74, -35, -18, -18, -11,  15,   4, -17,
+  -12,  17,  14,  17,  17,  38,  23,  11,
+   10,  17,  23,  15,  20,  45,  44,  13,
+   -8,  22,  24,  27,  26,  33,  26,   3,
+  -18,  -4,  21,  24,  27,  23,   9, -11,
+  -19,  -3,  11,  21,  23,  16,   7,  -9,
+  -27, -11,   4,  13,  14,   4,  -5, -17,
+  -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+// middlegame and endgame halves of a score, blended by the game phase at the end.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Score {
+  pub mg: i32,
+  pub eg: i32,
+}
+
+impl Score {
+  pub fn add(&mut self, mg: i32, eg: i32) {
+    self.mg += mg;
+    self.eg += eg;
+  }
+}
+
+pub fn phase(board: &chess::Board) -> i32 {
+  let mut phase = 0;
+  for piece in chess::ALL_PIECES.iter() {
+    phase += PHASE_INC[piece.to_index()] * board.pieces(*piece).popcnt() as i32;
+  }
+  return phase.min(MAX_PHASE);
+}
+
+pub fn taper(score: Score, phase: i32) -> i32 {
+  return (score.mg * phase + score.eg * (MAX_PHASE - phase)) / MAX_PHASE;
+}
+
+// material and piece-square tables for one side.
+fn material(board: &chess::Board, color: chess::Color, w: &Weights) -> Score {
+  let mut score = Score::default();
+  let flip = if color == chess::Color::White {56} else {0};
+  for piece in chess::ALL_PIECES.iter() {
+    let p = piece.to_index();
+    for sq in *board.pieces(*piece) & *board.color_combined(color) {
+      let i = sq.to_index() ^ flip;
+      score.add(w.mg_value[p] + w.mg_pst[p][i], w.eg_value[p] + w.eg_pst[p][i]);
+    }
+  }
+  return score;
+}
+
+// king safety, by how many enemy attack units hit the king zone.
+const SAFETY_TABLE: [i32; 100] = [
+    0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+   18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+   68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+  140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+  260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+  377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+  494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+  500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+  500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+  500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
+// attack units per attacked zone square, indexed like MG_VALUE.
+const ATTACK_WEIGHT: [i32; 6] = [0, 2, 2, 3, 5, 0];
+// by distance of the nearest pawn in front of the king on each of its files.
+const SHIELD: [i32; 8] = [0, 20, 10, 4, 0, 0, 0, 0];
+const STORM: [i32; 8] = [0, 0, -30, -15, -5, 0, 0, 0];
+const SHIELD_MISSING: i32 = -20;
+const KING_OPEN_FILE: i32 = -25;
+const KING_SEMI_OPEN_FILE: i32 = -12;
+
+// the pawn of bb closest to the king's rank, looking up the board from color.
+fn nearest(bb: chess::BitBoard, color: chess::Color) -> Option<chess::Square> {
+  if bb.popcnt() == 0 {
+    return None;
+  }
+  return Some(match color {
+    chess::Color::White => bb.to_square(),
+    chess::Color::Black => chess::ALL_SQUARES[63 - bb.0.leading_zeros() as usize],
+  });
+}
+
+fn shelter(board: &chess::Board, color: chess::Color, king: chess::Square, w: &Weights) -> i32 {
+  let pawns = *board.pieces(chess::Piece::Pawn);
+  let ours = pawns & *board.color_combined(color);
+  let theirs = pawns & *board.color_combined(!color);
+  let ahead = pawns::ranks_ahead(king.get_rank(), color);
+  let king_rank = pawns::relative_rank(king, color);
+
+  let mut score = 0;
+  let center = king.get_file().to_index().clamp(1, 6);
+  for f in center - 1..center + 2 {
+    let file = chess::get_file(chess::ALL_FILES[f]);
+    let own = nearest(ours & file & ahead, color);
+    let their = nearest(theirs & file & ahead, color);
+    match own {
+      Some(sq) => score += w.shield[pawns::relative_rank(sq, color) - king_rank],
+      None     => score += w.shield_missing,
+    }
+    if let Some(sq) = their {
+      let distance = pawns::relative_rank(sq, color) - king_rank;
+      // a storming pawn stuck on our shield pawn is less of a threat.
+      let blocked = own.is_some_and(|o| o.forward(color) == Some(sq));
+      score += if blocked {w.storm[distance] / 2} else {w.storm[distance]};
+    }
+    if (pawns & file).popcnt() == 0 {
+      score += w.king_open_file;
+    } else if (ours & file).popcnt() == 0 {
+      score += w.king_semi_open_file;
+    }
+  }
+  return score;
+}
+
+// safety of color's king, counting the attacks of the other side.
+fn king_safety(board: &chess::Board, color: chess::Color, w: &Weights) -> Score {
+  let king = board.king_square(color);
+  let zone = chess::get_king_moves(king) | chess::BitBoard::from_square(king);
+  let occupied = *board.combined();
+
+  let mut attackers = 0;
+  let mut units = 0;
+  for piece in [chess::Piece::Knight, chess::Piece::Bishop, chess::Piece::Rook, chess::Piece::Queen].iter() {
+    for sq in *board.pieces(*piece) & *board.color_combined(!color) {
+      let hits = attacks(*piece, sq, occupied) & zone;
+      if hits.popcnt() > 0 {
+        attackers += 1;
+        units += w.attack_weight[piece.to_index()] * hits.popcnt() as i32;
+      }
+    }
+  }
+
+  // a lone attacker rarely gets anywhere.
+  let danger = if attackers >= 2 {w.safety_table[units.min(99) as usize]} else {0};
+  return Score { mg: shelter(board, color, king, w) - danger, eg: -danger / 4 };
+}
+
+// every tunable evaluation parameter, mobility indexed by the number of safe squares.
+#[derive(Clone)]
+pub struct Weights {
+  pub mg_value:            [i32; 6],
+  pub eg_value:            [i32; 6],
+  pub mg_pst:              [[i32; 64]; 6],
+  pub eg_pst:              [[i32; 64]; 6],
+  pub passed_mg:           [i32; 8],
+  pub passed_eg:           [i32; 8],
+  pub connected:           [i32; 8],
+  pub isolated:            Score,
+  pub doubled:             Score,
+  pub backward:            Score,
+  pub island:              Score,
+  pub safety_table:        [i32; 100],
+  pub attack_weight:       [i32; 6],
+  pub shield:              [i32; 8],
+  pub storm:               [i32; 8],
+  pub shield_missing:      i32,
+  pub king_open_file:      i32,
+  pub king_semi_open_file: i32,
+  pub knight_mobility:     [Score; 9],
+  pub bishop_mobility:     [Score; 14],
+  pub rook_mobility:       [Score; 15],
+  pub queen_mobility:      [Score; 28],
+  pub bishop_pair:         Score,
+  pub rook_open_file:      Score,
+  pub rook_semi_open_file: Score,
+  pub rook_seventh:        Score,
+  pub knight_outpost:      Score,
+  pub bishop_outpost:      Score,
+  pub trapped_bishop:      Score,
+  pub trapped_rook:        Score,
+}
+
+const fn s(mg: i32, eg: i32) -> Score {
+  return Score { mg: mg, eg: eg };
+}
+
+pub static DEFAULT_WEIGHTS: Weights = Weights {
+  mg_value:            MG_VALUE,
+  eg_value:            EG_VALUE,
+  mg_pst:              [MG_PAWN, MG_KNIGHT, MG_BISHOP, MG_ROOK, MG_QUEEN, MG_KING],
+  eg_pst:              [EG_PAWN, EG_KNIGHT, EG_BISHOP, EG_ROOK, EG_QUEEN, EG_KING],
+  passed_mg:           pawns::PASSED_MG,
+  passed_eg:           pawns::PASSED_EG,
+  connected:           pawns::CONNECTED,
+  isolated:            pawns::ISOLATED,
+  doubled:             pawns::DOUBLED,
+  backward:            pawns::BACKWARD,
+  island:              pawns::ISLAND,
+  safety_table:        SAFETY_TABLE,
+  attack_weight:       ATTACK_WEIGHT,
+  shield:              SHIELD,
+  storm:               STORM,
+  shield_missing:      SHIELD_MISSING,
+  king_open_file:      KING_OPEN_FILE,
+  king_semi_open_file: KING_SEMI_OPEN_FILE,
+  knight_mobility: [
+    s(-31, -41), s(-27, -28), s(-6, -16), s(-2, -8), s(1, 2), s(6, 5), s(11, 8),
+    s(14, 10), s(16, 12),
+  ],
+  bishop_mobility: [
+    s(-24, -30), s(-10, -12), s(8, -2), s(13, 6), s(19, 12), s(25, 21), s(27, 27),
+    s(31, 28), s(31, 32), s(34, 36), s(40, 39), s(40, 43), s(45, 44), s(49, 48),
+  ],
+  rook_mobility: [
+    s(-29, -38), s(-14, -9), s(-8, 14), s(-5, 27), s(-3, 34), s(-1, 41), s(4, 56),
+    s(8, 59), s(15, 66), s(14, 71), s(16, 77), s(19, 82), s(23, 83), s(24, 84),
+    s(29, 85),
+  ],
+  queen_mobility: [
+    s(-20, -18), s(-11, -8), s(1, 4), s(1, 9), s(7, 17), s(11, 27), s(14, 30),
+    s(20, 36), s(21, 39), s(24, 46), s(28, 47), s(30, 52), s(30, 56), s(33, 60),
+    s(33, 61), s(35, 63), s(35, 66), s(36, 68), s(39, 70), s(44, 71), s(44, 74),
+    s(49, 83), s(51, 85), s(51, 87), s(53, 92), s(54, 95), s(56, 103), s(58, 106),
+  ],
+  bishop_pair:         s(30, 50),
+  rook_open_file:      s(25, 10),
+  rook_semi_open_file: s(12, 5),
+  rook_seventh:        s(10, 20),
+  knight_outpost:      s(25, 15),
+  bishop_outpost:      s(15, 8),
+  trapped_bishop:      s(-80, -80),
+  trapped_rook:        s(-45, -10),
+};
+
+impl Default for Weights {
+  fn default() -> Self {
+    return DEFAULT_WEIGHTS.clone();
+  }
+}
+
+fn push_ints<'a>(params: &mut Vec<(String, &'a mut i32)>, name: &str, values: &'a mut [i32]) {
+  for (i, v) in values.iter_mut().enumerate() {
+    params.push((format!("{}[{}]", name, i), v));
+  }
+}
+
+fn push_score<'a>(params: &mut Vec<(String, &'a mut i32)>, name: &str, score: &'a mut Score) {
+  params.push((format!("{}.mg", name), &mut score.mg));
+  params.push((format!("{}.eg", name), &mut score.eg));
+}
+
+fn push_scores<'a>(params: &mut Vec<(String, &'a mut i32)>, name: &str, scores: &'a mut [Score]) {
+  for (i, score) in scores.iter_mut().enumerate() {
+    push_score(params, &format!("{}[{}]", name, i), score);
+  }
+}
+
+impl Weights {
+  // every value by name, always in the same order, so the tuner can walk them.
+  pub fn params(&mut self) -> Vec<(String, &mut i32)> {
+    let Weights {
+      mg_value, eg_value, mg_pst, eg_pst, passed_mg, passed_eg, connected, isolated, doubled, backward, island,
+      safety_table, attack_weight, shield, storm, shield_missing, king_open_file, king_semi_open_file,
+      knight_mobility, bishop_mobility, rook_mobility, queen_mobility, bishop_pair, rook_open_file, rook_semi_open_file,
+      rook_seventh, knight_outpost, bishop_outpost, trapped_bishop, trapped_rook,
+    } = self;
+
+    let mut params = vec![];
+    push_ints(&mut params, "mg_value", mg_value);
+    push_ints(&mut params, "eg_value", eg_value);
+    for (p, table) in mg_pst.iter_mut().enumerate() {
+      push_ints(&mut params, &format!("mg_pst[{}]", p), table);
+    }
+    for (p, table) in eg_pst.iter_mut().enumerate() {
+      push_ints(&mut params, &format!("eg_pst[{}]", p), table);
+    }
+    push_ints(&mut params, "passed_mg", passed_mg);
+    push_ints(&mut params, "passed_eg", passed_eg);
+    push_ints(&mut params, "connected", connected);
+    push_score(&mut params, "isolated", isolated);
+    push_score(&mut params, "doubled", doubled);
+    push_score(&mut params, "backward", backward);
+    push_score(&mut params, "island", island);
+    push_ints(&mut params, "safety_table", safety_table);
+    push_ints(&mut params, "attack_weight", attack_weight);
+    push_ints(&mut params, "shield", shield);
+    push_ints(&mut params, "storm", storm);
+    params.push(("shield_missing".to_string(), shield_missing));
+    params.push(("king_open_file".to_string(), king_open_file));
+    params.push(("king_semi_open_file".to_string(), king_semi_open_file));
+    push_scores(&mut params, "knight_mobility", knight_mobility);
+    push_scores(&mut params, "bishop_mobility", bishop_mobility);
+    push_scores(&mut params, "rook_mobility", rook_mobility);
+    push_scores(&mut params, "queen_mobility", queen_mobility);
+    push_score(&mut params, "bishop_pair", bishop_pair);
+    push_score(&mut params, "rook_open_file", rook_open_file);
+    push_score(&mut params, "rook_semi_open_file", rook_semi_open_file);
+    push_score(&mut params, "rook_seventh", rook_seventh);
+    push_score(&mut params, "knight_outpost", knight_outpost);
+    push_score(&mut params, "bishop_outpost", bishop_outpost);
+    push_score(&mut params, "trapped_bishop", trapped_bishop);
+    push_score(&mut params, "trapped_rook", trapped_rook);
+    return params;
+  }
+
+  // reads "name value" lines on top of the defaults, '#' starts a comment.
+  pub fn load(path: &str) -> Result<Self, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let mut weights = Weights::default();
+    {
+      let mut params: HashMap<String, &mut i32> = weights.params().into_iter().collect();
+      for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+          continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let value = match (tokens.len(), tokens.last().and_then(|v| v.parse::<i32>().ok())) {
+          (2, Some(v)) => v,
+          _            => return Err(format!("{}:{}: expected a name and an integer", path, n + 1)),
+        };
+        match params.get_mut(tokens[0]) {
+          Some(p) => **p = value,
+          None    => return Err(format!("{}:{}: unknown parameter {}", path, n + 1, tokens[0])),
+        }
+      }
+    }
+    return Ok(weights);
+  }
+
+  pub fn save(&self, path: &str) -> Result<(), String> {
+    let mut weights = self.clone();
+    let mut text = String::new();
+    for (name, value) in weights.params() {
+      text.push_str(&format!("{} {}\n", name, value));
+    }
+    return fs::write(path, text).map_err(|e| format!("cannot write {}: {}", path, e));
+  }
+}
+
+fn attacks(piece: chess::Piece, sq: chess::Square, occupied: chess::BitBoard) -> chess::BitBoard {
+  return match piece {
+    chess::Piece::Knight => chess::get_knight_moves(sq),
+    chess::Piece::Bishop => chess::get_bishop_moves(sq, occupied),
+    chess::Piece::Rook   => chess::get_rook_moves(sq, occupied),
+    chess::Piece::Queen  => chess::get_bishop_moves(sq, occupied) | chess::get_rook_moves(sq, occupied),
+    _                    => chess::EMPTY,
+  };
+}
+
+// a bishop on a7 or h7 that an enemy pawn has cut off (a2 or h2 for black).
+fn trapped_bishop(sq: chess::Square, color: chess::Color, theirs: chess::BitBoard) -> bool {
+  let (trap, pawn) = match (color, sq) {
+    (chess::Color::White, chess::Square::A7) => (true, chess::Square::B6),
+    (chess::Color::White, chess::Square::H7) => (true, chess::Square::G6),
+    (chess::Color::Black, chess::Square::A2) => (true, chess::Square::B3),
+    (chess::Color::Black, chess::Square::H2) => (true, chess::Square::G3),
+    _                                        => (false, sq),
+  };
+  return trap && (theirs & chess::BitBoard::from_square(pawn)).popcnt() > 0;
+}
+
+// a rook hemmed in on the king's side of the back rank once castling is gone.
+fn trapped_rook(board: &chess::Board, sq: chess::Square, color: chess::Color, mobility: u32) -> bool {
+  let king = board.king_square(color);
+  if mobility > 3 || board.castle_rights(color) != chess::CastleRights::NoRights {
+    return false;
+  }
+  if pawns::relative_rank(king, color) != 0 || pawns::relative_rank(sq, color) != 0 {
+    return false;
+  }
+  let king_file = king.get_file().to_index();
+  let rook_file = sq.get_file().to_index();
+  return (king_file >= 4 && rook_file > king_file) || (king_file < 4 && rook_file < king_file);
+}
+
+fn pieces(board: &chess::Board, color: chess::Color, w: &Weights) -> Score {
+  let mut score = Score::default();
+  let occupied = *board.combined();
+  let pawns = *board.pieces(chess::Piece::Pawn);
+  let ours = pawns & *board.color_combined(color);
+  let theirs = pawns & *board.color_combined(!color);
+  let our_attacks = pawns::pawn_attacks(ours, color);
+  // squares that are neither ours nor covered by an enemy pawn.
+  let area = !*board.color_combined(color) & !pawns::pawn_attacks(theirs, !color);
+
+  if (*board.pieces(chess::Piece::Bishop) & *board.color_combined(color)).popcnt() >= 2 {
+    score.add(w.bishop_pair.mg, w.bishop_pair.eg);
+  }
+
+  for piece in [chess::Piece::Knight, chess::Piece::Bishop, chess::Piece::Rook, chess::Piece::Queen].iter() {
+    for sq in *board.pieces(*piece) & *board.color_combined(color) {
+      let mobility = (attacks(*piece, sq, occupied) & area).popcnt();
+      let m = mobility as usize;
+      let bonus = match piece {
+        chess::Piece::Knight => w.knight_mobility[m],
+        chess::Piece::Bishop => w.bishop_mobility[m],
+        chess::Piece::Rook   => w.rook_mobility[m],
+        _                    => w.queen_mobility[m],
+      };
+      score.add(bonus.mg, bonus.eg);
+
+      let rank = pawns::relative_rank(sq, color);
+      let file = chess::get_file(sq.get_file());
+      let square = chess::BitBoard::from_square(sq);
+      match piece {
+        chess::Piece::Knight | chess::Piece::Bishop => {
+          // supported by a pawn and out of reach of every enemy pawn.
+          let safe = (theirs & chess::get_adjacent_files(sq.get_file()) & pawns::ranks_ahead(sq.get_rank(), color)).popcnt() == 0;
+          if (3..6).contains(&rank) && (our_attacks & square).popcnt() > 0 && safe {
+            let outpost = if *piece == chess::Piece::Knight {w.knight_outpost} else {w.bishop_outpost};
+            score.add(outpost.mg, outpost.eg);
+          }
+          if *piece == chess::Piece::Bishop && trapped_bishop(sq, color, theirs) {
+            score.add(w.trapped_bishop.mg, w.trapped_bishop.eg);
+          }
+        },
+        chess::Piece::Rook => {
+          if (pawns & file).popcnt() == 0 {
+            score.add(w.rook_open_file.mg, w.rook_open_file.eg);
+          } else if (ours & file).popcnt() == 0 {
+            score.add(w.rook_semi_open_file.mg, w.rook_semi_open_file.eg);
+          }
+          if rank == 6 {
+            score.add(w.rook_seventh.mg, w.rook_seventh.eg);
+          }
+          if trapped_rook(board, sq, color, mobility) {
+            score.add(w.trapped_rook.mg, w.trapped_rook.eg);
+          }
+        },
+        _ => {},
+      }
+    }
+  }
+  return score;
+}
+
+// static evaluation from white's point of view.
+pub fn evaluate(board: &chess::Board, pawn_table: &mut pawns::PawnTable, w: &Weights) -> i32 {
+  let entry = pawn_table.probe(board, w);
+  return evaluate_with(board, &entry, w);
+}
+
+// skips the pawn hash, whose entries only hold for the weights they were computed with.
+pub fn evaluate_uncached(board: &chess::Board, w: &Weights) -> i32 {
+  let entry = pawns::evaluate(board, pawns::pawn_key(board), w);
+  return evaluate_with(board, &entry, w);
+}
+
+fn evaluate_with(board: &chess::Board, entry: &pawns::PawnEntry, w: &Weights) -> i32 {
+  if let Some(score) = endgame::evaluate(board) {
+    return score;
+  }
+
+  let white = material(board, chess::Color::White, w);
+  let black = material(board, chess::Color::Black, w);
+  let mut score = Score { mg: white.mg - black.mg, eg: white.eg - black.eg };
+
+  let white_passed = pawns::passed(board, entry, chess::Color::White, w);
+  let black_passed = pawns::passed(board, entry, chess::Color::Black, w);
+  score.add(entry.score.mg + white_passed.mg - black_passed.mg, entry.score.eg + white_passed.eg - black_passed.eg);
+
+  let white_king = king_safety(board, chess::Color::White, w);
+  let black_king = king_safety(board, chess::Color::Black, w);
+  score.add(white_king.mg - black_king.mg, white_king.eg - black_king.eg);
+
+  let white_pieces = pieces(board, chess::Color::White, w);
+  let black_pieces = pieces(board, chess::Color::Black, w);
+  score.add(white_pieces.mg - black_pieces.mg, white_pieces.eg - black_pieces.eg);
+
+  let strong = if score.eg >= 0 {chess::Color::White} else {chess::Color::Black};
+  score.eg = score.eg * endgame::scale_factor(board, strong) / endgame::SCALE_NORMAL;
+  return taper(score, phase(board));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  #[test]
+  fn trapped_rooks() {
+    let board = chess::Board::from_str("4k3/8/8/8/8/8/6PP/5K1R w - - 0 1").unwrap();
+    assert!(trapped_rook(&board, chess::Square::H1, chess::Color::White, 1));
+    // the same rook with room to breathe.
+    assert!(!trapped_rook(&board, chess::Square::H1, chess::Color::White, 4));
+    // boxed in, but off the back rank.
+    let board = chess::Board::from_str("4k3/8/8/7p/6P1/6PR/7P/5K2 w - - 0 1").unwrap();
+    assert!(!trapped_rook(&board, chess::Square::H3, chess::Color::White, 1));
+    // black's back rank is the eighth.
+    let board = chess::Board::from_str("5k1r/6pp/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+    assert!(trapped_rook(&board, chess::Square::H8, chess::Color::Black, 1));
+  }
+}
@@ -0,0 +1,99 @@
+extern crate chess;
+
+// every history score stays within +-MAX_HISTORY.
+pub static MAX_HISTORY: i32 = 16384;
+static MAX_BONUS: i32 = 1200;
+// coloured pieces times squares, the key of a continuation entry.
+static PIECE_SQUARES: usize = 12 * 64;
+
+// a move together with the coloured piece that made it, which is what the continuation tables are keyed by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PieceMove {
+  pub piece: usize,
+  pub m:     chess::ChessMove,
+}
+
+impl PieceMove {
+  // must be called before m is made.
+  pub fn new(board: &chess::Board, m: chess::ChessMove) -> Self {
+    let piece = board.piece_on(m.get_source()).map_or(0, |p| p.to_index());
+    return Self { piece: piece + 6 * board.side_to_move().to_index(), m: m };
+  }
+
+  fn index(&self) -> usize {
+    return self.piece * 64 + self.m.get_dest().to_index();
+  }
+}
+
+#[derive(Clone)]
+pub struct HistoryHeuristics {
+  // [side][from][to], for quiet moves that caused or failed to cause a cutoff.
+  butterfly:         Vec<i32>,
+  // [piece and destination of the move before][piece and destination of the reply], shared by
+  // the replies to the opponent's last move and to our own move before it.
+  continuation:      Vec<i32>,
+  // the quiet reply that last refuted each [piece][destination] move.
+  pub counter_moves: Vec<Option<chess::ChessMove>>,
+  // the last two quiet moves that caused a cutoff at each ply.
+  pub killers:       Vec<[Option<chess::ChessMove>; 2]>,
+}
+
+// pulls the entry towards +-MAX_HISTORY, slower the closer it already is.
+fn gravity(entry: &mut i32, bonus: i32) {
+  *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+}
+
+fn bonus(depth: i32) -> i32 {
+  return (16 * depth * depth).min(MAX_BONUS);
+}
+
+impl HistoryHeuristics {
+  pub fn new(max_ply: usize) -> Self {
+    return Self {
+      butterfly:     vec![0; 2 * 64 * 64],
+      continuation:  vec![0; PIECE_SQUARES * PIECE_SQUARES],
+      counter_moves: vec![None; PIECE_SQUARES],
+      killers:       vec![[None; 2]; max_ply],
+    };
+  }
+
+  fn butterfly_index(side: chess::Color, m: chess::ChessMove) -> usize {
+    return (side.to_index() * 64 + m.get_source().to_index()) * 64 + m.get_dest().to_index();
+  }
+
+  // butterfly plus the 1 and 2 ply continuation scores of a quiet move.
+  pub fn score(&self, side: chess::Color, m: PieceMove, previous: &[Option<PieceMove>]) -> i32 {
+    let mut score = self.butterfly[Self::butterfly_index(side, m.m)];
+    for p in previous.iter().flatten() {
+      score += self.continuation[p.index() * PIECE_SQUARES + m.index()];
+    }
+    return score;
+  }
+
+  pub fn is_killer(&self, ply: usize, m: chess::ChessMove) -> bool {
+    return self.killers[ply].contains(&Some(m));
+  }
+
+  pub fn counter_move(&self, previous: Option<PieceMove>) -> Option<chess::ChessMove> {
+    return previous.and_then(|p| self.counter_moves[p.index()]);
+  }
+
+  // rewards the quiet move that caused a cutoff and punishes the quiets tried before it.
+  pub fn update(&mut self, side: chess::Color, ply: usize, depth: i32, best: PieceMove, tried: &[PieceMove], previous: &[Option<PieceMove>]) {
+    let bonus = bonus(depth);
+    for m in tried.iter() {
+      let delta = if *m == best {bonus} else {-bonus};
+      gravity(&mut self.butterfly[Self::butterfly_index(side, m.m)], delta);
+      for p in previous.iter().flatten() {
+        gravity(&mut self.continuation[p.index() * PIECE_SQUARES + m.index()], delta);
+      }
+    }
+    if self.killers[ply][0] != Some(best.m) {
+      self.killers[ply][1] = self.killers[ply][0];
+      self.killers[ply][0] = Some(best.m);
+    }
+    if let Some(p) = previous.first().copied().flatten() {
+      self.counter_moves[p.index()] = Some(best.m);
+    }
+  }
+}
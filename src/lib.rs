@@ -0,0 +1,16 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::clone_on_copy, clippy::len_zero, clippy::too_many_arguments, clippy::new_without_default)]
+pub mod uci;
+pub mod search;
+pub mod sthread;
+pub mod timeman;
+pub mod tt;
+pub mod eval;
+pub mod pawns;
+pub mod endgame;
+pub mod syzygy;
+pub mod book;
+pub mod see;
+pub mod history;
+pub mod movepick;
+pub mod perft;
+pub mod bench;
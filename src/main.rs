@@ -1,10 +1,49 @@
-mod uci;
-pub mod search;
-pub mod sthread;
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::clone_on_copy, clippy::len_zero, clippy::too_many_arguments, clippy::new_without_default)]
+extern crate ce;
 extern crate chess;
+use ce::{uci, perft, bench, book};
+use std::str::FromStr;
 
-#[allow(unused_must_use)]
 fn main() {
-  let mut uci_handle = uci::UciFunctions::new();
-  uci_handle.go(100, -1, -1, false);
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if args.len() > 0 {
+    run_command(&args);
+    return;
+  }
+  let mut uci_handle = uci::UciParser::new();
+  uci_handle.run();
+}
+
+// usage: ce perft|divide <depth> [fen], ce bench [depth], ce book <pgn> <output> [depth]
+fn run_command(args: &[String]) {
+  match args[0].as_str() {
+    "perft" | "divide" => {
+      let depth: usize = args.get(1).and_then(|d| d.parse().ok()).unwrap_or(1);
+      let board = if args.len() > 2 {
+        match chess::Board::from_str(&args[2..].join(" ")) {
+          Ok(b)  => b,
+          Err(_) => {
+            println!("invalid fen {}", args[2..].join(" "));
+            return;
+          },
+        }
+      } else {
+        chess::Board::default()
+      };
+      perft::run(&board, depth, args[0] == "divide");
+    },
+    "bench" => bench::run(args.get(1).and_then(|d| d.parse().ok()).unwrap_or(bench::DEFAULT_BENCH_DEPTH)),
+    "book" => {
+      if args.len() < 3 {
+        println!("usage: ce book <pgn> <output> [depth]");
+        return;
+      }
+      let depth: usize = args.get(3).and_then(|d| d.parse().ok()).unwrap_or(book::DEFAULT_BOOK_DEPTH);
+      match book::build(&args[1], &args[2], 2 * depth) {
+        Ok(n)  => println!("wrote {} entries to {}", n, args[2]),
+        Err(e) => println!("{}", e),
+      }
+    },
+    _ => println!("unknown command {}", args[0]),
+  }
 }
\ No newline at end of file
@@ -0,0 +1,231 @@
+extern crate chess;
+use history;
+use see;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Stage {
+  TtMove,
+  GenerateCaptures,
+  GoodCaptures,
+  FirstKiller,
+  SecondKiller,
+  Counter,
+  GenerateQuiets,
+  Quiets,
+  BadCaptures,
+  Done,
+}
+
+// hands out the moves of a position best first, generating each group only once the ones before
+// it are used up, which a cutoff often makes unnecessary.
+pub struct MovePicker {
+  board:    chess::Board,
+  gen:      chess::MoveGen,
+  stage:    Stage,
+  tt_move:  Option<chess::ChessMove>,
+  killers:  [Option<chess::ChessMove>; 2],
+  counter:  Option<chess::ChessMove>,
+  previous: [Option<history::PieceMove>; 2],
+  // scored moves of the current stage, the ones before index are already handed out.
+  moves:    Vec<(chess::ChessMove, i32)>,
+  index:    usize,
+  bad:      Vec<(chess::ChessMove, i32)>,
+  // quiet moves the capture generation ran into, scored with the rest of the quiets.
+  stashed:  Vec<chess::ChessMove>,
+}
+
+// most valuable victim first, least valuable attacker breaking ties.
+pub fn mvv_lva(board: &chess::Board, m: chess::ChessMove) -> i32 {
+  let attacker = board.piece_on(m.get_source()).map_or(0, |p| see::SEE_VALUE[p.to_index()]);
+  return see::gain(board, m) * 10 - attacker / 100;
+}
+
+fn is_noisy(board: &chess::Board, m: chess::ChessMove) -> bool {
+  return see::is_capture(board, m) || m.get_promotion().is_some();
+}
+
+impl MovePicker {
+  pub fn new(board: chess::Board, tt_move: Option<chess::ChessMove>, history: &history::HistoryHeuristics, ply: usize, previous: [Option<history::PieceMove>; 2]) -> Self {
+    return Self {
+      board:    board,
+      gen:      chess::MoveGen::new_legal(&board),
+      stage:    Stage::TtMove,
+      tt_move:  tt_move.filter(|m| board.legal(*m)),
+      killers:  history.killers[ply],
+      counter:  history.counter_move(previous[0]),
+      previous: previous,
+      moves:    vec![],
+      index:    0,
+      bad:      vec![],
+      stashed:  vec![],
+    };
+  }
+
+  // how many legal moves the position has, only meaningful before the first call to next.
+  pub fn legal_moves(&self) -> usize {
+    return self.gen.len();
+  }
+
+  // moves already handed out by an earlier stage.
+  fn seen(&self, m: chess::ChessMove, stage: Stage) -> bool {
+    return Some(m) == self.tt_move
+      || (stage > Stage::FirstKiller && Some(m) == self.killers[0])
+      || (stage > Stage::SecondKiller && Some(m) == self.killers[1])
+      || (stage > Stage::Counter && Some(m) == self.counter);
+  }
+
+  // a killer or counter-move from another position, worth trying if it is a legal quiet move here.
+  fn refutation(&self, m: Option<chess::ChessMove>) -> Option<chess::ChessMove> {
+    return m.filter(|m| !self.seen(*m, self.stage) && self.board.legal(*m) && !is_noisy(&self.board, *m));
+  }
+
+  // swaps the best remaining move to the front of the list and hands it out.
+  fn pick_best(&mut self) -> Option<(chess::ChessMove, i32)> {
+    if self.index >= self.moves.len() {
+      return None;
+    }
+    let mut best = self.index;
+    for i in self.index + 1..self.moves.len() {
+      if self.moves[i].1 > self.moves[best].1 {
+        best = i;
+      }
+    }
+    self.moves.swap(self.index, best);
+    self.index += 1;
+    return Some(self.moves[self.index - 1]);
+  }
+
+  fn generate_captures(&mut self) {
+    let side = self.board.side_to_move();
+    let mut targets = *self.board.color_combined(!side);
+    if let Some(sq) = self.board.en_passant().and_then(|sq| sq.forward(side)) {
+      targets |= chess::BitBoard::from_square(sq);
+    }
+    let promotions = !*self.board.combined() & chess::get_rank(side.to_their_backrank());
+    self.gen.set_iterator_mask(targets | promotions);
+    let mut moves = vec![];
+    for m in &mut self.gen {
+      if !is_noisy(&self.board, m) {
+        self.stashed.push(m);
+      } else if Some(m) != self.tt_move {
+        moves.push((m, mvv_lva(&self.board, m)));
+      }
+    }
+    self.moves = moves;
+    self.index = 0;
+  }
+
+  fn generate_quiets(&mut self, history: &history::HistoryHeuristics) {
+    self.gen.set_iterator_mask(!chess::EMPTY);
+    let mut quiets: Vec<chess::ChessMove> = std::mem::take(&mut self.stashed);
+    quiets.extend(&mut self.gen);
+    let side = self.board.side_to_move();
+    self.moves = quiets.into_iter()
+      .filter(|m| !self.seen(*m, Stage::Quiets))
+      .map(|m| (m, history.score(side, history::PieceMove::new(&self.board, m), &self.previous)))
+      .collect();
+    self.index = 0;
+  }
+
+  pub fn next(&mut self, history: &history::HistoryHeuristics) -> Option<chess::ChessMove> {
+    loop {
+      match self.stage {
+        Stage::TtMove => {
+          self.stage = Stage::GenerateCaptures;
+          if self.tt_move.is_some() {
+            return self.tt_move;
+          }
+        },
+        Stage::GenerateCaptures => {
+          self.generate_captures();
+          self.stage = Stage::GoodCaptures;
+        },
+        Stage::GoodCaptures => {
+          match self.pick_best() {
+            // losing captures wait until every quiet move has had its turn.
+            Some((m, score)) if !see::see(&self.board, m, 0) => self.bad.push((m, score)),
+            Some((m, _)) => return Some(m),
+            None         => self.stage = Stage::FirstKiller,
+          }
+        },
+        Stage::FirstKiller => {
+          let m = self.refutation(self.killers[0]);
+          self.stage = Stage::SecondKiller;
+          if m.is_some() {
+            return m;
+          }
+        },
+        Stage::SecondKiller => {
+          let m = self.refutation(self.killers[1]);
+          self.stage = Stage::Counter;
+          if m.is_some() {
+            return m;
+          }
+        },
+        Stage::Counter => {
+          let m = self.refutation(self.counter);
+          self.stage = Stage::GenerateQuiets;
+          if m.is_some() {
+            return m;
+          }
+        },
+        Stage::GenerateQuiets => {
+          self.generate_quiets(history);
+          self.stage = Stage::Quiets;
+        },
+        Stage::Quiets => {
+          match self.pick_best() {
+            Some((m, _)) => return Some(m),
+            None         => {
+              self.moves = std::mem::take(&mut self.bad);
+              self.index = 0;
+              self.stage = Stage::BadCaptures;
+            },
+          }
+        },
+        Stage::BadCaptures => {
+          // already in the order they were found, best mvv-lva first.
+          if self.index < self.moves.len() {
+            self.index += 1;
+            return Some(self.moves[self.index - 1].0);
+          }
+          self.stage = Stage::Done;
+        },
+        Stage::Done => return None,
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  #[test]
+  fn every_move_once() {
+    let fens = [
+      "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+      "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+      "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+      "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    ];
+    for fen in fens.iter() {
+      let board = chess::Board::from_str(fen).unwrap();
+      let legal: Vec<chess::ChessMove> = chess::MoveGen::new_legal(&board).collect();
+      let mut history = history::HistoryHeuristics::new(4);
+      // a quiet killer, a capture killer and a tt move, all of which must still come out only once.
+      let quiet = legal.iter().copied().find(|m| !is_noisy(&board, *m));
+      let capture = legal.iter().copied().find(|m| is_noisy(&board, *m));
+      history.killers[1] = [quiet, capture];
+      let mut picker = MovePicker::new(board, legal.last().copied(), &history, 1, [None, None]);
+      let mut picked = vec![];
+      while let Some(m) = picker.next(&history) {
+        picked.push(m);
+      }
+      assert_eq!(picked[0], *legal.last().unwrap());
+      assert_eq!(picked.len(), legal.len());
+      assert!(legal.iter().all(|m| picked.contains(m)));
+    }
+  }
+}
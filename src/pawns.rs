@@ -0,0 +1,186 @@
+extern crate chess;
+use eval::{Score, Weights};
+
+// the chess crate's get_pawn_hash is not implemented, so pawn keys use their own zobrist table.
+static PAWN_ZOBRIST: [[u64; 64]; 2] = zobrist_table();
+static PAWN_TABLE_SIZE: usize = 1 << 14;
+
+// default bonuses by relative rank, the passed one halved when the pawn is blocked.
+pub const PASSED_MG: [i32; 8] = [0, 5, 10, 15, 30, 55, 90, 0];
+pub const PASSED_EG: [i32; 8] = [0, 10, 15, 30, 55, 90, 140, 0];
+pub const CONNECTED: [i32; 8] = [0, 3, 5, 8, 14, 24, 40, 0];
+pub const ISOLATED: Score = Score { mg: -6, eg: -14 };
+pub const DOUBLED: Score = Score { mg: -10, eg: -24 };
+pub const BACKWARD: Score = Score { mg: -8, eg: -10 };
+pub const ISLAND: Score = Score { mg: -4, eg: -8 };
+
+const fn zobrist_table() -> [[u64; 64]; 2] {
+  let mut table = [[0; 64]; 2];
+  // splitmix64
+  let mut seed: u64 = 0x9e3779b97f4a7c15;
+  let mut i = 0;
+  while i < 128 {
+    seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    table[i / 64][i % 64] = z ^ (z >> 31);
+    i += 1;
+  }
+  return table;
+}
+
+pub fn pawn_key(board: &chess::Board) -> u64 {
+  let mut key: u64 = 0;
+  for color in chess::ALL_COLORS.iter() {
+    for sq in *board.pieces(chess::Piece::Pawn) & *board.color_combined(*color) {
+      key ^= PAWN_ZOBRIST[color.to_index()][sq.to_index()];
+    }
+  }
+  return key;
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PawnEntry {
+  key:        u64,
+  // white's point of view.
+  pub score:  Score,
+  pub passed: [chess::BitBoard; 2],
+}
+
+// per thread cache of the pawn structure terms, to be cleared whenever the weights change.
+#[derive(Clone)]
+pub struct PawnTable {
+  entries: Vec<Option<PawnEntry>>,
+}
+
+impl PawnTable {
+  pub fn new() -> Self {
+    return Self { entries: vec![None; PAWN_TABLE_SIZE] };
+  }
+
+  pub fn clear(&mut self) {
+    for entry in self.entries.iter_mut() {
+      *entry = None;
+    }
+  }
+
+  pub fn probe(&mut self, board: &chess::Board, w: &Weights) -> PawnEntry {
+    let key = pawn_key(board);
+    let index = key as usize % self.entries.len();
+    if let Some(entry) = self.entries[index] {
+      if entry.key == key {
+        return entry;
+      }
+    }
+    let entry = evaluate(board, key, w);
+    self.entries[index] = Some(entry);
+    return entry;
+  }
+}
+
+// every rank in front of the given one, seen from color.
+pub fn ranks_ahead(rank: chess::Rank, color: chess::Color) -> chess::BitBoard {
+  let r = rank.to_index();
+  return chess::BitBoard(match color {
+    chess::Color::White => if r >= 7 {0} else {!0u64 << (8 * (r + 1))},
+    chess::Color::Black => (1u64 << (8 * r)) - 1,
+  });
+}
+
+pub fn relative_rank(sq: chess::Square, color: chess::Color) -> usize {
+  let r = sq.get_rank().to_index();
+  return if color == chess::Color::White {r} else {7 - r};
+}
+
+// squares an enemy pawn must not occupy or control for a pawn on sq to be passed.
+pub fn passed_span(sq: chess::Square, color: chess::Color) -> chess::BitBoard {
+  let files = chess::get_file(sq.get_file()) | chess::get_adjacent_files(sq.get_file());
+  return files & ranks_ahead(sq.get_rank(), color);
+}
+
+pub fn pawn_attacks(pawns: chess::BitBoard, color: chess::Color) -> chess::BitBoard {
+  let mut attacks = chess::EMPTY;
+  for sq in pawns {
+    attacks |= chess::get_pawn_attacks(sq, color, !chess::EMPTY);
+  }
+  return attacks;
+}
+
+fn evaluate_side(board: &chess::Board, color: chess::Color, w: &Weights) -> (Score, chess::BitBoard) {
+  let mut score = Score::default();
+  let mut passed = chess::EMPTY;
+  let pawns = *board.pieces(chess::Piece::Pawn);
+  let ours = pawns & *board.color_combined(color);
+  let theirs = pawns & *board.color_combined(!color);
+  let their_attacks = pawn_attacks(theirs, !color);
+
+  for sq in ours {
+    let file = sq.get_file();
+    let rank = relative_rank(sq, color);
+    let neighbours = ours & chess::get_adjacent_files(file);
+    let stop = sq.forward(color);
+
+    if (theirs & passed_span(sq, color)).popcnt() == 0 && (ours & chess::get_file(file) & ranks_ahead(sq.get_rank(), color)).popcnt() == 0 {
+      passed |= chess::BitBoard::from_square(sq);
+    }
+
+    if neighbours.popcnt() == 0 {
+      score.add(w.isolated.mg, w.isolated.eg);
+    } else {
+      // no neighbour level with or behind it, and it can't safely step up to them.
+      let behind = !ranks_ahead(sq.get_rank(), color);
+      let stop_attacked = stop.is_some_and(|s| (their_attacks & chess::BitBoard::from_square(s)).popcnt() > 0);
+      if (neighbours & behind).popcnt() == 0 && stop_attacked {
+        score.add(w.backward.mg, w.backward.eg);
+      }
+    }
+
+    let supported = (ours & chess::get_pawn_attacks(sq, !color, !chess::EMPTY)).popcnt() > 0;
+    let phalanx = (neighbours & chess::get_rank(sq.get_rank())).popcnt() > 0;
+    if supported {
+      score.add(w.connected[rank], w.connected[rank]);
+    } else if phalanx {
+      score.add(w.connected[rank] / 2, w.connected[rank] / 2);
+    }
+  }
+
+  let mut islands = 0;
+  let mut in_island = false;
+  for f in chess::ALL_FILES.iter() {
+    let count = (ours & chess::get_file(*f)).popcnt() as i32;
+    if count > 1 {
+      score.add(w.doubled.mg * (count - 1), w.doubled.eg * (count - 1));
+    }
+    if count > 0 && !in_island {
+      islands += 1;
+    }
+    in_island = count > 0;
+  }
+  if islands > 1 {
+    score.add(w.island.mg * (islands - 1), w.island.eg * (islands - 1));
+  }
+  return (score, passed);
+}
+
+pub fn evaluate(board: &chess::Board, key: u64, w: &Weights) -> PawnEntry {
+  let (white, white_passed) = evaluate_side(board, chess::Color::White, w);
+  let (black, black_passed) = evaluate_side(board, chess::Color::Black, w);
+  return PawnEntry { key: key, score: Score { mg: white.mg - black.mg, eg: white.eg - black.eg }, passed: [white_passed, black_passed] };
+}
+
+// passed pawns are only cached as a bitboard, whether they are blocked depends on the pieces.
+pub fn passed(board: &chess::Board, entry: &PawnEntry, color: chess::Color, w: &Weights) -> Score {
+  let mut score = Score::default();
+  for sq in entry.passed[color.to_index()] {
+    let rank = relative_rank(sq, color);
+    let blocked = sq.forward(color).is_some_and(|s| board.piece_on(s).is_some());
+    let (mg, eg) = (w.passed_mg[rank], w.passed_eg[rank]);
+    if blocked {
+      score.add(mg / 2, eg / 2);
+    } else {
+      score.add(mg, eg);
+    }
+  }
+  return score;
+}
@@ -0,0 +1,104 @@
+extern crate chess;
+use std::time::SystemTime;
+
+// counts the leaf nodes of the legal move tree, playing every move like the uci position command does.
+pub fn perft(board: &chess::Board, depth: usize) -> u64 {
+  let moves = chess::MoveGen::new_legal(board);
+  if depth <= 1 {
+    return if depth == 0 {1} else {moves.len() as u64};
+  }
+  let mut nodes: u64 = 0;
+  for m in moves {
+    let mut result: chess::Board = *board;
+    board.make_move(m, &mut result);
+    nodes += perft(&result, depth - 1);
+  }
+  return nodes;
+}
+
+// perft split by root move.
+pub fn divide(board: &chess::Board, depth: usize) -> Vec<(chess::ChessMove, u64)> {
+  let mut counts = vec![];
+  for m in chess::MoveGen::new_legal(board) {
+    let mut result: chess::Board = *board;
+    board.make_move(m, &mut result);
+    counts.push((m, perft(&result, depth.max(1) - 1)));
+  }
+  return counts;
+}
+
+pub fn run(board: &chess::Board, depth: usize, split: bool) {
+  let start = SystemTime::now();
+  let nodes = if split {
+    let counts = divide(board, depth);
+    for (m, n) in counts.iter() {
+      println!("{}: {}", m, n);
+    }
+    println!();
+    counts.iter().map(|c| c.1).sum()
+  } else {
+    perft(board, depth)
+  };
+  let duration: u128 = start.elapsed().unwrap().as_millis();
+  println!("Nodes searched: {}", nodes);
+  println!("info string perft depth {} nodes {} time {} nps {}", depth, nodes, duration, nodes as u128 * 1000 / duration.max(1));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+  use uci;
+
+  fn check(fen: &str, expected: &[u64]) {
+    let board = chess::Board::from_str(fen).unwrap();
+    for (i, n) in expected.iter().enumerate() {
+      assert_eq!(perft(&board, i + 1), *n, "{} at depth {}", fen, i + 1);
+    }
+  }
+
+  #[test]
+  fn startpos() {
+    check("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281]);
+  }
+
+  #[test]
+  fn kiwipete() {
+    check("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862]);
+  }
+
+  #[test]
+  fn en_passant_and_pins() {
+    check("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238]);
+  }
+
+  #[test]
+  fn promotions() {
+    check("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467]);
+    check("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379]);
+  }
+
+  #[test]
+  fn middlegame() {
+    check("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890]);
+  }
+
+  #[test]
+  fn divide_sums_to_perft() {
+    let board = chess::Board::default();
+    let counts = divide(&board, 3);
+    assert_eq!(counts.len(), 20);
+    assert_eq!(counts.iter().map(|c| c.1).sum::<u64>(), perft(&board, 3));
+  }
+
+  #[test]
+  fn position_with_moves() {
+    // the last double push allows e5xf6 en passant.
+    let mut uci = uci::UciFunctions::new();
+    uci.position(String::new(), true, "e2e4 d7d5 e4e5 f7f5".to_string());
+    let expected = chess::Board::from_str("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+    assert_eq!(uci.board(), expected);
+    assert_eq!(perft(&uci.board(), 3), perft(&expected, 3));
+    assert!(divide(&uci.board(), 1).iter().any(|c| c.0.to_string() == "e5f6"));
+  }
+}
@@ -1,103 +1,219 @@
 extern crate num_cpus;
 extern crate chess;
 use sthread;
-use std::{time::SystemTime, vec};
+use std::vec;
 use std::collections::HashMap;
 use std::sync::{mpsc, Arc};
-use std::sync::atomic::{AtomicBool, AtomicI32};
-use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::{thread, time};
+use uci;
+use tt;
+use pawns;
+use eval;
+use syzygy;
+use history;
 
 pub struct Search {
-  nodes: i32,
-  tt   : HashMap<i32, sthread::TtEntry>,
-  history
-       : sthread::HistoryHeuristics,
+  tt   : Arc<tt::TranspositionTable>,
+  weights: Arc<eval::Weights>,
+  tb: Option<Arc<syzygy::Tablebases>>,
+  tbhits: Arc<AtomicU64>,
   //thread_pool: rayon::ThreadPoolBuilder
-  pruned: i32,
-  receivers: Vec<mpsc::Receiver<sthread::ThreadMessage>>,
-  senders: Vec<mpsc::Sender<sthread::ThreadMessage>>,
-  threads: Vec<sthread::ThreadManager>
+  threads: Vec<sthread::ThreadManager>,
+  should_stop: Arc<AtomicBool>,
+  // the running search hands the thread managers back once it is joined.
+  handle: Option<thread::JoinHandle<Vec<sthread::ThreadManager>>>
 }
 
 impl Search {
   pub fn new() -> Self {
-    // setup counter_moves heuristic table
-    let mut cm: Vec<Vec<i32>> = vec![];
-    let mut ks: Vec<Vec<chess::ChessMove>> = vec![];
-    for _ in 0..64 {
-      cm.push([1; 64].to_vec());
-      ks.push([chess::ChessMove::new(chess::Square::A1, chess::Square::A1, None); 64].to_vec());
+    let cpus = num_cpus::get_physical();
+    println!("info string Detected {} cores", cpus);
+
+    let should_stop = Arc::new(AtomicBool::new(false));
+    let mut search = Self { tt: Arc::new(tt::TranspositionTable::new(tt::DEFAULT_HASH_MB)), weights: Arc::new(eval::Weights::default()), tb: None, tbhits: Arc::new(AtomicU64::new(0)), threads: vec![], should_stop: should_stop, handle: None };
+    search.set_threads(cpus);
+    return search;
+  }
+
+  pub fn set_threads(&mut self, count: usize) {
+    self.stop();
+    let pool_nodes: Vec<Arc<AtomicU64>> = (0..count.max(1)).map(|_| Arc::new(AtomicU64::new(0))).collect();
+    self.threads = vec![];
+    for id in 0..pool_nodes.len() {
+      self.threads.push(sthread::ThreadManager { id: id, nodes: pool_nodes[id].clone(), pool_nodes: pool_nodes.clone(), should_stop: self.should_stop.clone(), stopper: sthread::Stopper::new(&uci::GoParams::default(), chess::Color::White), tt: self.tt.clone(), sender: None, score: 0, pv: vec![vec![]; sthread::MAX_PLY], seldepth: 0, stats: sthread::SearchStats::default(), pawns: pawns::PawnTable::new(), weights: self.weights.clone(), tb: self.tb.clone(), tbhits: self.tbhits.clone(), played: vec![None; sthread::MAX_PLY], evals: vec![None; sthread::MAX_PLY], nmp_min_ply: 0, history: history::HistoryHeuristics::new(sthread::MAX_PLY) });
     }
+  }
 
-    let mut recvs: Vec<mpsc::Receiver<sthread::ThreadMessage>> = Vec::<mpsc::Receiver::<sthread::ThreadMessage>>::new();
-    let mut sends: Vec<mpsc::Sender<sthread::ThreadMessage>> = Vec::<mpsc::Sender::<sthread::ThreadMessage>>::new();
-    let mut threads: Vec<sthread::ThreadManager> = vec![];
+  pub fn set_weights(&mut self, weights: eval::Weights) {
+    self.stop();
+    self.weights = Arc::new(weights);
+    for tm in self.threads.iter_mut() {
+      tm.weights = self.weights.clone();
+      tm.pawns.clear();
+    }
+  }
 
-    let cpus = num_cpus::get_physical();
-    println!("Detected {} cores", cpus);
+  // an empty path unloads the tablebases, otherwise returns the largest piece count they cover.
+  pub fn set_syzygy_path(&mut self, path: &str) -> Result<usize, String> {
+    self.stop();
+    self.tb = if path.is_empty() {None} else {Some(Arc::new(syzygy::Tablebases::new(path)?))};
+    for tm in self.threads.iter_mut() {
+      tm.tb = self.tb.clone();
+    }
+    return Ok(self.tb.as_ref().map_or(0, |tb| tb.max_pieces()));
+  }
 
-    let should_stop = Arc::new(AtomicBool::new(false));
+  pub fn thread_count(&self) -> usize {
+    return self.threads.len();
+  }
 
-    for id in 0..cpus {
-      let (send, recv) = mpsc::channel();
-      recvs.push(recv);
-      sends.push(send);
-      threads.push(sthread::ThreadManager { id: id, nodes: Arc::new(AtomicI32::new(0)), should_stop: should_stop.clone(), sender: send, score: 0, history: sthread::HistoryHeuristics { counter_moves: cm, killers: ks } });
+  pub fn set_hash(&mut self, mb: usize) {
+    self.stop();
+    self.tt = Arc::new(tt::TranspositionTable::new(mb));
+    for tm in self.threads.iter_mut() {
+      tm.tt = self.tt.clone();
     }
+  }
 
-    return Self { nodes: 0, tt: HashMap::new(), history: sthread::HistoryHeuristics { counter_moves: cm, killers: ks }, pruned: 0, receivers: recvs, senders: sends, threads: threads };
+  // per thread statistics of the last search, None while a search is still running.
+  pub fn stats(&mut self) -> Option<Vec<sthread::SearchStats>> {
+    if self.handle.as_ref().is_some_and(|h| !h.is_finished()) {
+      return None;
+    }
+    self.stop();
+    return Some(self.threads.iter().map(|tm| tm.stats()).collect());
   }
 
-pub fn search_pos(&mut self, board: chess::Board, alpha: i32, beta: i32) {
-  let mut iterable = chess::MoveGen::new_legal(&board);
-  for t in 0..self.receivers.len() {
-    let mut result: chess::Board = board.clone();
-    board.make_move(m, &mut result);
-    thread::spawn(move || {
-      sthread::iterative_deepening(*mut self.threads[t], result, alpha, beta);
-    });
+  pub fn clear(&mut self) {
+    self.stop();
+    self.tt.clear();
+    for tm in self.threads.iter_mut() {
+      tm.history = history::HistoryHeuristics::new(sthread::MAX_PLY);
+    }
   }
-}
 
-/*
-pub fn iterative_deepening(&mut self, board: chess::Board, alpha: i32, beta: i32, stopper: Stopper) -> (i32, chess::ChessMove) {
-  let mut result: (i32, chess::ChessMove) = (0, chess::ChessMove::new(chess::Square::A1, chess::Square::A1, None));
-  let start = SystemTime::now();
-  for d in 2..stopper.depth+1 {
-    result = self.search(board, d as i32, alpha, beta, stopper);
-    let duration: u128 = start.elapsed().unwrap().as_millis();
-    println!("info depth {} score cp {} nodes {} pruned {} nps {} time {} pv {}", d, result.0, self.nodes, self.pruned, (self.nodes as f32 /((duration as f32 /1000 as f32)) as f32) as i32, duration, result.1);
+pub fn search_pos(&mut self, board: chess::Board, alpha: i32, beta: i32, params: uci::GoParams) {
+  self.stop();
+  self.should_stop.store(false, Ordering::Relaxed);
+  self.tt.new_search();
+
+  self.tbhits.store(0, Ordering::Relaxed);
+
+  // only search the root moves that keep the tablebase result.
+  let mut params = params;
+  if let Some(moves) = self.tb.as_ref().and_then(|tb| tb.root_moves(&board)) {
+    let keep: Vec<chess::ChessMove> = moves.into_iter().filter(|m| params.searchmoves.len() == 0 || params.searchmoves.contains(m)).collect();
+    if keep.len() > 0 {
+      params.searchmoves = keep;
+    }
   }
-  self.nodes = 0;
 
-  return result;
-}
+  let (send, recv) = mpsc::channel();
+  let stopper = sthread::Stopper::new(&params, board.side_to_move());
+  let mut threads = std::mem::take(&mut self.threads);
+  for tm in threads.iter_mut() {
+    tm.stopper = stopper.clone();
+    tm.nodes.store(0, Ordering::Relaxed);
+    tm.stats = sthread::SearchStats::default();
+    tm.nmp_min_ply = 0;
+    tm.sender = Some(send.clone());
+  }
+  drop(send);
 
-fn search(&mut self, board: chess::Board, depth: i32, mut alpha: i32, beta: i32, stopper: Stopper) -> (i32, chess::ChessMove) {
-  let mut iterable = self.order(board);
-  let color = if board.side_to_move() == chess::Color::Black {-1} else {1};  
-  let mut best: chess::ChessMove = chess::ChessMove::default();
-  for m in &mut iterable {
-    if stopper.should_stop {
-      break;
+  let should_stop = self.should_stop.clone();
+  self.handle = Some(thread::spawn(move || {
+    let mut main = threads.remove(0);
+    let helpers: Vec<thread::JoinHandle<sthread::ThreadManager>> = threads.into_iter().map(|mut tm| {
+      thread::spawn(move || {
+        sthread::iterative_deepening(&mut tm, board, alpha, beta);
+        return tm;
+      })
+    }).collect();
+
+    let main_best = sthread::iterative_deepening(&mut main, board, alpha, beta);
+    // under go infinite the bestmove may only be sent after the gui told us to stop.
+    while main.stopper.infinite && !should_stop.load(Ordering::Relaxed) {
+      thread::sleep(time::Duration::from_millis(1));
+    }
+    should_stop.store(true, Ordering::Relaxed);
+
+    let mut threads = vec![main];
+    for h in helpers {
+      threads.push(h.join().unwrap());
     }
-    let mut result: chess::Board = board.clone();
-    board.make_move(m, &mut result);
-    let r: i32 = -self.alpha_beta(result, 0, depth, -beta, -alpha, -color, stopper, 0);
-    //println!("{} {}", m, r);
-    if r >= beta {
-      return (beta, m);
+    for tm in threads.iter_mut() {
+      tm.sender = None;
     }
-    if r > alpha {
-      alpha = r;
-      best = m;
+
+    match Search::vote(recv.try_iter().collect()).or(main_best) {
+      Some(m) => println!("bestmove {}", m),
+      None    => println!("bestmove 0000"),
     }
+    return threads;
+  }));
+}
+
+// picks the move backed by the deepest and best scoring threads, using each thread's last completed iteration.
+fn vote(messages: Vec<sthread::ThreadMessage>) -> Option<chess::ChessMove> {
+  let mut last: HashMap<usize, sthread::ThreadMessage> = HashMap::new();
+  for msg in messages {
+    last.insert(msg.id, msg);
   }
-  return (alpha, best);
+  let min_score = last.values().map(|r| r.score).min()?;
+  let mut votes: HashMap<chess::ChessMove, i64> = HashMap::new();
+  for r in last.values() {
+    *votes.entry(r.best).or_insert(0) += (r.score - min_score + 14) as i64 * r.depth as i64;
+  }
+  // ties go to the lowest thread id, so a single thread always plays its own move.
+  let mut ids: Vec<&usize> = last.keys().collect();
+  ids.sort();
+  let mut best: Option<(chess::ChessMove, i64)> = None;
+  for id in ids {
+    let m = last[id].best;
+    if best.is_none_or(|b| votes[&m] > b.1) {
+      best = Some((m, votes[&m]));
+    }
+  }
+  return best.map(|b| b.0);
+}
+
+pub fn stop(&mut self) {
+  self.should_stop.store(true, Ordering::Relaxed);
+  self.wait();
 }
 
-fn add_to_tt(&mut self, pos: chess::Board, entry: TtEntry) {
-  let h: i32 = pos.get_hash() as i32;
-  self.tt.insert(h, entry);
-}*/
-}
\ No newline at end of file
+// blocks until the running search finishes on its own.
+pub fn wait(&mut self) {
+  if let Some(handle) = self.handle.take() {
+    self.threads = handle.join().unwrap();
+  }
+}
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  // the score and line of a fixed depth search on a single thread.
+  fn search(fen: &str, depth: i16) -> (i32, Vec<chess::ChessMove>) {
+    let mut searcher = Search::new();
+    searcher.set_threads(1);
+    let params = uci::GoParams { depth: Some(depth), ..uci::GoParams::default() };
+    searcher.search_pos(chess::Board::from_str(fen).unwrap(), -sthread::INFINITE, sthread::INFINITE, params);
+    searcher.wait();
+    return (searcher.threads[0].score, searcher.threads[0].pv[0].clone());
+  }
+
+  #[test]
+  fn stalemate_before_pruning() {
+    // Rc1+ Kxc1 leaves black without a legal move.
+    let fen = "7k/5Q2/2r5/8/8/3p4/PP1P4/1K6 b - - 0 1";
+    for depth in 2..8 {
+      let (score, pv) = search(fen, depth);
+      assert_eq!(score, 0, "depth {}", depth);
+      assert_eq!(pv[0], chess::ChessMove::from_str("c6c1").unwrap(), "depth {}", depth);
+    }
+  }
+}
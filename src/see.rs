@@ -0,0 +1,138 @@
+extern crate chess;
+
+// exchange values, indexed by chess::Piece::to_index.
+pub static SEE_VALUE: [i32; 6] = [100, 320, 330, 500, 900, 20000];
+
+// every piece of either colour attacking sq, sliders seen through the given occupancy.
+pub fn attackers(board: &chess::Board, sq: chess::Square, occupied: chess::BitBoard) -> chess::BitBoard {
+  let pawns = *board.pieces(chess::Piece::Pawn);
+  let bishops = *board.pieces(chess::Piece::Bishop) | *board.pieces(chess::Piece::Queen);
+  let rooks = *board.pieces(chess::Piece::Rook) | *board.pieces(chess::Piece::Queen);
+  return (chess::get_pawn_attacks(sq, chess::Color::White, !chess::EMPTY) & pawns & *board.color_combined(chess::Color::Black))
+    | (chess::get_pawn_attacks(sq, chess::Color::Black, !chess::EMPTY) & pawns & *board.color_combined(chess::Color::White))
+    | (chess::get_knight_moves(sq) & *board.pieces(chess::Piece::Knight))
+    | (chess::get_king_moves(sq) & *board.pieces(chess::Piece::King))
+    | (chess::get_bishop_moves(sq, occupied) & bishops)
+    | (chess::get_rook_moves(sq, occupied) & rooks);
+}
+
+pub fn is_en_passant(board: &chess::Board, m: chess::ChessMove) -> bool {
+  return board.piece_on(m.get_source()) == Some(chess::Piece::Pawn)
+    && board.piece_on(m.get_dest()).is_none()
+    && m.get_source().get_file() != m.get_dest().get_file();
+}
+
+pub fn is_capture(board: &chess::Board, m: chess::ChessMove) -> bool {
+  return board.piece_on(m.get_dest()).is_some() || is_en_passant(board, m);
+}
+
+// material m wins if nothing recaptures.
+pub fn gain(board: &chess::Board, m: chess::ChessMove) -> i32 {
+  let victim = match board.piece_on(m.get_dest()) {
+    Some(p) => SEE_VALUE[p.to_index()],
+    None if is_en_passant(board, m) => SEE_VALUE[chess::Piece::Pawn.to_index()],
+    None    => 0,
+  };
+  let promotion = m.get_promotion().map_or(0, |p| SEE_VALUE[p.to_index()] - SEE_VALUE[chess::Piece::Pawn.to_index()]);
+  return victim + promotion;
+}
+
+// whether the exchange started by m on its destination square wins at least threshold.
+pub fn see(board: &chess::Board, m: chess::ChessMove, threshold: i32) -> bool {
+  let from = m.get_source();
+  let to = m.get_dest();
+  let mut swap = gain(board, m) - threshold;
+  if swap < 0 {
+    return false;
+  }
+  // a promoted pawn stands on the square as its new piece.
+  let moving = match m.get_promotion() {
+    Some(p) => SEE_VALUE[p.to_index()],
+    None    => board.piece_on(from).map_or(0, |p| SEE_VALUE[p.to_index()]),
+  };
+  swap = moving - swap;
+  if swap <= 0 {
+    return true;
+  }
+
+  let bishops = *board.pieces(chess::Piece::Bishop) | *board.pieces(chess::Piece::Queen);
+  let rooks = *board.pieces(chess::Piece::Rook) | *board.pieces(chess::Piece::Queen);
+  let mut occupied = *board.combined() ^ chess::BitBoard::from_square(from) ^ chess::BitBoard::from_square(to);
+  if is_en_passant(board, m) {
+    // the taken pawn leaves its own square, which may open a rank behind it.
+    occupied ^= chess::BitBoard::from_square(chess::Square::make_square(from.get_rank(), to.get_file()));
+  }
+  let mut attacking = attackers(board, to, occupied);
+  let mut side = board.side_to_move();
+  // 1 while the side that made the last capture is winning the exchange.
+  let mut result = 1;
+  loop {
+    side = !side;
+    attacking &= occupied;
+    let ours = attacking & *board.color_combined(side);
+    if ours.popcnt() == 0 {
+      break;
+    }
+    result ^= 1;
+
+    // recapture with the least valuable piece, uncovering any slider behind it.
+    let piece = chess::ALL_PIECES.iter().find(|p| (ours & *board.pieces(**p)).popcnt() > 0).unwrap();
+    if *piece == chess::Piece::King {
+      // the king may only take last.
+      return if (attacking & *board.color_combined(!side)).popcnt() > 0 {result ^ 1 == 1} else {result == 1};
+    }
+    swap = SEE_VALUE[piece.to_index()] - swap;
+    if swap < result {
+      break;
+    }
+    occupied ^= chess::BitBoard::from_square((ours & *board.pieces(*piece)).to_square());
+    match piece {
+      chess::Piece::Pawn | chess::Piece::Bishop => attacking |= chess::get_bishop_moves(to, occupied) & bishops,
+      chess::Piece::Rook                        => attacking |= chess::get_rook_moves(to, occupied) & rooks,
+      chess::Piece::Queen                       => attacking |= (chess::get_bishop_moves(to, occupied) & bishops) | (chess::get_rook_moves(to, occupied) & rooks),
+      _                                         => {},
+    }
+  }
+  return result == 1;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn see_value(fen: &str, m: &str) -> i32 {
+    let board = chess::Board::from_str(fen).unwrap();
+    let m = chess::ChessMove::from_str(m).unwrap();
+    assert!(board.legal(m));
+    // the largest threshold the exchange still reaches.
+    let mut low = -SEE_VALUE[5];
+    let mut high = SEE_VALUE[5];
+    while low < high {
+      let mid = (low + high + 1).div_euclid(2);
+      if see(&board, m, mid) {low = mid} else {high = mid - 1}
+    }
+    return low;
+  }
+
+  #[test]
+  fn exchanges() {
+    // undefended pawn.
+    assert_eq!(see_value("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+    // knight for a pawn, the bishop and queen behind the rook join in.
+    assert_eq!(see_value("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"), -220);
+    // quiet move onto a square only a pawn guards.
+    assert_eq!(see_value("4k3/8/2p5/8/8/8/8/3RK3 w - - 0 1", "d1d5"), -500);
+    // x-rayed rooks: rook takes rook, rook takes back, the second rook recaptures.
+    assert_eq!(see_value("3rk3/3r4/8/8/8/8/3R4/3RK3 w - - 0 1", "d2d7"), 0);
+    // the king can't take back a defended piece.
+    assert_eq!(see_value("4k3/4p3/3P4/8/8/8/8/4RK2 w - - 0 1", "d6e7"), 100);
+  }
+
+  #[test]
+  fn special_moves() {
+    assert_eq!(see_value("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+    assert_eq!(see_value("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+    assert_eq!(see_value("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), -100);
+  }
+}
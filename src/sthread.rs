@@ -1,258 +1,680 @@
-use std::sync::{mpsc, Arc};
-use std::sync::atomic::{AtomicBool, AtomicI32};
-use std::{time::SystemTime, vec};
-use std::collections::HashMap;
+use std::sync::{mpsc, Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::{time::Instant, vec};
+use uci;
+use timeman;
+use tt;
+use eval;
+use pawns;
+use syzygy;
+use see;
+use history;
+use movepick;
 extern crate chess;
+extern crate shakmaty_syzygy;
 
-static MAX_DEPTH: i16 = 100;
+pub static MAX_DEPTH: i16 = 100;
+// deepest ply the search can reach, quiescence included.
+pub const MAX_PLY: usize = 128;
+// a mate at ply n scores MATE - n, anything beyond MATE_BOUND is a forced mate.
+pub const MATE: i32 = 32000;
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+pub const INFINITE: i32 = MATE + 1;
+// tablebase wins score below every mate.
+pub const TB_WIN: i32 = MATE_BOUND - MAX_PLY as i32;
+// qsearch skips captures that can't raise alpha even when they win this much more.
+static DELTA_MARGIN: i32 = 200;
+// near the leaves, captures losing more than this per ply of depth left are not searched.
+static SEE_PRUNE_MARGIN: i32 = 100;
+static SEE_PRUNE_DEPTH: i32 = 6;
+// null move pruning kicks in from this depth, and verifies its cutoffs from VERIFY_DEPTH on.
+static NMP_DEPTH: i32 = 3;
+static NMP_VERIFY_DEPTH: i32 = 12;
+// late move reductions start at this depth, after this many moves.
+static LMR_DEPTH: i32 = 3;
+static LMR_MOVES: usize = 2;
+// quiet moves beyond the late move pruning count are skipped up to this depth.
+static LMP_DEPTH: i32 = 4;
+// history scores worth one ply of reduction.
+static LMR_HISTORY_DIVISOR: i32 = 8192;
+// aspiration windows start this wide around the previous score, from this depth on.
+static ASPIRATION_WINDOW: i32 = 25;
+static ASPIRATION_DEPTH: i16 = 4;
+// how many nodes are searched between two looks at the clock.
+static CHECK_INTERVAL: u64 = 1024;
+// lazy smp depth skipping for the helper threads, so they don't all search the same iteration.
+static SKIP_SIZE: [i16; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+static SKIP_PHASE: [i16; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Stopper {
-  pub st:          SystemTime,
-  pub nodes:       i32,
+  pub st:          Instant,
+  pub nodes:       Option<u64>,
   pub depth:       i16,
-  pub time:        i16,
-  pub should_stop: bool,
+  pub time:        Option<timeman::TimeManager>,
+  pub mate:        Option<i16>,
+  pub infinite:    bool,
+  pub searchmoves: Vec<chess::ChessMove>,
 }
 
-#[derive(Clone)]
-pub struct ThreadMessage {
-  // type of message
-  get_status: bool,
-  mission:    bool,
+impl Stopper {
+  pub fn new(params: &uci::GoParams, side: chess::Color) -> Self {
+    let mut depth = params.depth.unwrap_or(MAX_DEPTH);
+    if let Some(mate) = params.mate {
+      // a mate in n moves is found within 2n - 1 plies.
+      depth = depth.min((2 * mate as i32 - 1).clamp(1, MAX_DEPTH as i32) as i16);
+    }
+
+    return Self { st: Instant::now(), nodes: params.nodes, depth: depth.max(1).min(MAX_DEPTH), time: timeman::TimeManager::new(params, side), mate: params.mate, infinite: params.infinite, searchmoves: params.searchmoves.clone() };
+  }
 
+  pub fn elapsed(&self) -> u128 {
+    return self.st.elapsed().as_millis();
+  }
 }
 
+// reported by every thread after each completed iteration.
 #[derive(Clone)]
-pub struct TtEntry {
-  eval: i32
+pub struct ThreadMessage {
+  pub id:    usize,
+  pub depth: i16,
+  pub score: i32,
+  pub best:  chess::ChessMove,
 }
 
-#[derive(Clone)]
-pub struct HistoryHeuristics {
-  pub counter_moves: Vec<Vec<i32>>,
-  pub killers:       Vec<Vec<chess::ChessMove>>
+// per thread counters, read back once the search is over.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchStats {
+  pub id:       usize,
+  pub nodes:    u64,
+  pub qnodes:   u64,
+  pub rfp:      u64,
+  pub futility: u64,
+  pub delta:    u64,
+  pub nmp:      u64,
+  pub lmp:      u64,
 }
 
 #[derive(Clone)]
 pub struct ThreadManager {
-  id: usize,
-  nodes: Arc<AtomicI32>,
-  should_stop: Arc<AtomicBool>,
-  sender: mpsc::Sender<ThreadMessage>,
-  score: i32,
-  history
-       : HistoryHeuristics,
+  pub id: usize,
+  pub nodes: Arc<AtomicU64>,
+  // node counters of every thread in the pool, this one included.
+  pub pool_nodes: Vec<Arc<AtomicU64>>,
+  pub should_stop: Arc<AtomicBool>,
+  pub stopper: Stopper,
+  pub tt: Arc<tt::TranspositionTable>,
+  pub sender: Option<mpsc::Sender<ThreadMessage>>,
+  pub score: i32,
+  // triangular principal variation table, pv[ply] holds the line found from that ply on.
+  pub pv: Vec<Vec<chess::ChessMove>>,
+  pub seldepth: usize,
+  pub stats: SearchStats,
+  pub pawns: pawns::PawnTable,
+  pub weights: Arc<eval::Weights>,
+  pub tb: Option<Arc<syzygy::Tablebases>>,
+  // tablebase hits of the whole pool.
+  pub tbhits: Arc<AtomicU64>,
+  // the move played at each ply of the current line, None for a null move.
+  pub played: Vec<Option<history::PieceMove>>,
+  // static eval at each ply of the current line, None where the side to move was in check.
+  pub evals: Vec<Option<i32>>,
+  // null moves are off before this ply while a verification search runs.
+  pub nmp_min_ply: usize,
+  pub history: history::HistoryHeuristics,
 }
 
-pub fn iterative_deepening(tm: *mut ThreadManager, board: chess::Board, alpha: i32, beta: i32) {
-  let color = if board.side_to_move() == chess::Color::Black {-1} else {1};
-  for d in 2..MAX_DEPTH {
-    unsafe{(*tm).score = alpha_beta(tm, board, 0, d as i32, alpha, beta, color, 0)};
+impl ThreadManager {
+  pub fn is_main(&self) -> bool {
+    return self.id == 0;
+  }
+
+  pub fn total_nodes(&self) -> u64 {
+    return self.pool_nodes.iter().map(|n| n.load(Ordering::Relaxed)).sum();
+  }
+
+  pub fn stats(&self) -> SearchStats {
+    return SearchStats { id: self.id, nodes: self.nodes.load(Ordering::Relaxed), ..self.stats };
+  }
+
+  fn update_pv(&mut self, ply: usize, m: chess::ChessMove) {
+    let (head, tail) = self.pv.split_at_mut(ply + 1);
+    head[ply].clear();
+    head[ply].push(m);
+    if let Some(child) = tail.first() {
+      head[ply].extend_from_slice(child);
+    }
+  }
+
+  // lines cut short by transposition table hits are completed from the table.
+  fn extend_pv(&mut self, board: chess::Board, depth: i16) {
+    let mut pos = board;
+    let mut seen = vec![pos.get_hash()];
+    for m in self.pv[0].iter() {
+      pos = pos.make_move_new(*m);
+      seen.push(pos.get_hash());
+    }
+    while self.pv[0].len() < depth as usize {
+      let m = match self.tt.probe(pos.get_hash()).and_then(|e| e.best) {
+        Some(m) if pos.legal(m) => m,
+        _ => break,
+      };
+      pos = pos.make_move_new(m);
+      if seen.contains(&pos.get_hash()) {
+        break;
+      }
+      seen.push(pos.get_hash());
+      self.pv[0].push(m);
+    }
+  }
+
+  fn report(&self, depth: i16, score: i32, bound: tt::Bound) {
+    let elapsed = self.stopper.elapsed();
+    let nodes = self.total_nodes();
+    let nps = nodes as u128 * 1000 / elapsed.max(1);
+    let pv: Vec<String> = self.pv[0].iter().map(|m| m.to_string()).collect();
+    let bound = match bound {
+      tt::Bound::Lower => " lowerbound",
+      tt::Bound::Upper => " upperbound",
+      tt::Bound::Exact => "",
+    };
+    println!("info depth {} seldepth {} score {}{} nodes {} nps {} time {} hashfull {} tbhits {} pv {}", depth, self.seldepth.max(depth as usize), uci_score(score), bound, nodes, nps, elapsed, self.tt.hashfull(), self.tbhits.load(Ordering::Relaxed), pv.join(" "));
   }
 }
 
-fn alpha_beta(mut tm: *mut ThreadManager, board: chess::Board, curr_depth: i32, max_depth: i32, mut alpha: i32, beta: i32, color: i8, previous_static_eval: i32) -> i32 {
-  let mut iterable = order(tm, board);
-  if curr_depth == max_depth || iterable.len() == 0 {
-    return quiesce(board, alpha, beta, color, 0);
+pub fn uci_score(score: i32) -> String {
+  if score >= MATE_BOUND {
+    return format!("mate {}", (MATE - score + 1) / 2);
+  } else if score <= -MATE_BOUND {
+    return format!("mate {}", -(MATE + score) / 2);
   }
-  let eval = eval(board) * color as i32;
-  let improving: bool = board.checkers().popcnt() == 0 && eval > previous_static_eval;
-  if curr_depth < 4 && eval - 225 * curr_depth + 100 * improving as i32 >= beta {
-    // Reverse Futility Pruning
-    return eval;
+  return format!("cp {}", score);
+}
+
+// mate and tablebase scores are stored relative to the node rather than the root, so they stay valid at any ply.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+  if score >= TB_WIN - MAX_PLY as i32 {
+    return score + ply as i32;
+  } else if score <= -(TB_WIN - MAX_PLY as i32) {
+    return score - ply as i32;
   }
-  let distance_to_leaf = max_depth - curr_depth;
-  if curr_depth > 1 && board.checkers().popcnt() == 0 && distance_to_leaf < 4 && eval + 300*distance_to_leaf < beta {
-    // Futility Pruning
-    return eval;
+  return score;
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+  if score >= TB_WIN - MAX_PLY as i32 {
+    return score - ply as i32;
+  } else if score <= -(TB_WIN - MAX_PLY as i32) {
+    return score + ply as i32;
   }
-  let mut value: i32 = -10000;
-  for m in &mut iterable {
-    let r: i32;
+  return score;
+}
+
+pub fn iterative_deepening(tm: &mut ThreadManager, board: chess::Board, alpha: i32, beta: i32) -> Option<chess::ChessMove> {
+  // fall back to the first root move in case even the first iteration gets interrupted.
+  let mut best: Option<chess::ChessMove> = root_moves(tm, board).next();
+  for d in 1..tm.stopper.depth+1 {
+    if !tm.is_main() {
+      let i = (tm.id - 1) % SKIP_SIZE.len();
+      if ((d + SKIP_PHASE[i]) / SKIP_SIZE[i]) % 2 != 0 {
+        continue;
+      }
+    }
+    let result = aspiration(tm, board, d, alpha, beta);
+    if tm.should_stop.load(Ordering::Relaxed) {
+      break;
+    }
+    let duration: u128 = tm.stopper.elapsed();
+    tm.score = result.0;
+    if let Some(m) = result.1 {
+      best = Some(m);
+      if let Some(sender) = tm.sender.as_ref() {
+        let _ = sender.send(ThreadMessage { id: tm.id, depth: d, score: result.0, best: m });
+      }
+      if tm.is_main() {
+        tm.extend_pv(board, d);
+        tm.report(d, result.0, tt::Bound::Exact);
+        if let Some(time) = tm.stopper.time.as_mut() {
+          time.update(m, result.0);
+        }
+      }
+    }
+    // only the main thread decides when the search is over.
+    if tm.is_main() && tm.stopper.time.as_ref().is_some_and(|t| !t.should_continue(duration)) {
+      break;
+    }
+    if tm.stopper.mate.is_some_and(|n| result.0 >= MATE_BOUND && (MATE - result.0 + 1) / 2 <= n as i32) {
+      break;
+    }
+  }
+  return best;
+}
+
+// searches a narrow window around the last score first, widening whichever side the score falls out of.
+fn aspiration(tm: &mut ThreadManager, board: chess::Board, depth: i16, alpha: i32, beta: i32) -> (i32, Option<chess::ChessMove>) {
+  if depth < ASPIRATION_DEPTH || tm.score.abs() >= TB_WIN - MAX_PLY as i32 {
+    return search_root(tm, board, depth as i32, alpha, beta);
+  }
+  let mut delta = ASPIRATION_WINDOW;
+  let mut low = (tm.score - delta).max(alpha);
+  let mut high = (tm.score + delta).min(beta);
+  loop {
+    let result = search_root(tm, board, depth as i32, low, high);
+    if tm.should_stop.load(Ordering::Relaxed) {
+      return result;
+    }
+    delta *= 2;
+    if result.0 <= low && low > alpha {
+      if tm.is_main() {
+        tm.report(depth, low, tt::Bound::Upper);
+      }
+      high = (low + high) / 2;
+      low = (result.0 - delta).max(alpha);
+    } else if result.0 >= high && high < beta {
+      if tm.is_main() {
+        tm.report(depth, high, tt::Bound::Lower);
+      }
+      high = (result.0 + delta).min(beta);
+    } else {
+      return result;
+    }
+  }
+}
+
+fn root_moves(tm: &ThreadManager, board: chess::Board) -> impl Iterator<Item = chess::ChessMove> {
+  let searchmoves = tm.stopper.searchmoves.clone();
+  let tt_move = tm.tt.probe(board.get_hash()).and_then(|e| e.best);
+  let mut picker = movepick::MovePicker::new(board, tt_move, &tm.history, 0, [None, None]);
+  let mut moves = vec![];
+  while let Some(m) = picker.next(&tm.history) {
+    moves.push(m);
+  }
+  return moves.into_iter().filter(move |m| searchmoves.len() == 0 || searchmoves.contains(m));
+}
+
+fn search_root(tm: &mut ThreadManager, board: chess::Board, depth: i32, mut alpha: i32, beta: i32) -> (i32, Option<chess::ChessMove>) {
+  let color = if board.side_to_move() == chess::Color::Black {-1} else {1};
+  let mut best: Option<chess::ChessMove> = None;
+  let root_pv = tm.pv[0].clone();
+  let original_alpha = alpha;
+  tm.seldepth = 0;
+  tm.evals[0] = if board.checkers().popcnt() > 0 {None} else {Some(eval::evaluate(&board, &mut tm.pawns, &tm.weights) * color as i32)};
+  for m in root_moves(tm, board) {
     let mut result: chess::Board = board.clone();
-    //if self.tt.contains_key(&(result.get_hash() as i32)) {
-    //  self.not_searched += 1;
-    //  r = self.tt.get(&(result.get_hash() as i32)).unwrap().eval;
-    //} else {
-    //  self.searched += 1;
     board.make_move(m, &mut result);
-    r = -alpha_beta(tm, result, curr_depth + 1, max_depth, -beta, -alpha, -color, eval);
-    //}
-    if curr_depth == 0 {
-      unsafe{(*tm).history.counter_moves[m.get_source().to_index()][m.get_dest().to_index()] = r};
+    tm.played[0] = Some(history::PieceMove::new(&board, m));
+    let mut r: i32 = if best.is_none() {-INFINITE} else {-alpha_beta(tm, result, 1, depth, -alpha - 1, -alpha, -color)};
+    if best.is_none() || (r > alpha && !tm.should_stop.load(Ordering::Relaxed)) {
+      r = -alpha_beta(tm, result, 1, depth, -beta, -alpha, -color);
     }
-    if r > value {
-      value = r;
-    }
-    if value > alpha {
-      alpha = value;
-      /*if !self.tt.contains_key(&(result.get_hash() as i32)) {
-        self.add_to_tt(result, TtEntry { eval: r });
-      }*/
+    if tm.should_stop.load(Ordering::Relaxed) {
+      break;
     }
     if r >= beta {
-      unsafe{(*tm).history.killers[m.get_source().to_index()][m.get_dest().to_index()] = m.clone()};
-      return beta;
+      tm.update_pv(0, m);
+      return (beta, Some(m));
+    }
+    if r > alpha || best.is_none() {
+      alpha = r;
+      best = Some(m);
+      tm.update_pv(0, m);
     }
   }
-  return value;
+  if tm.should_stop.load(Ordering::Relaxed) {
+    // an interrupted iteration keeps the line of the last completed one, and its score is no bound at this depth.
+    tm.pv[0] = root_pv;
+    return (alpha, best);
+  }
+  if best.is_some() {
+    let bound = if alpha > original_alpha {tt::Bound::Exact} else {tt::Bound::Upper};
+    tm.tt.store(board.get_hash(), best, score_to_tt(alpha, 0), eval::evaluate(&board, &mut tm.pawns, &tm.weights) * color as i32, depth as i16, bound);
+  }
+  return (alpha, best);
 }
 
-fn quiesce(board: chess::Board, mut alpha: i32, beta: i32, color: i8, depth: i32) -> i32 {
-  let stand_pat: i32 = eval(board) * color as i32;
-  if stand_pat >= beta {
-    return beta;
-  }
-  if alpha < stand_pat  {
-    alpha = stand_pat;
+fn alpha_beta(tm: &mut ThreadManager, board: chess::Board, curr_depth: i32, max_depth: i32, mut alpha: i32, beta: i32, color: i8) -> i32 {
+  if should_stop(tm) {
+    return 0;
   }
-  let prune_delta: i32 = 1000;
-  if stand_pat < alpha - prune_delta {
+  let ply = curr_depth as usize;
+  tm.pv[ply].clear();
+  tm.seldepth = tm.seldepth.max(ply);
+
+  // mate distance pruning, no line from here can beat a shorter mate found elsewhere.
+  alpha = alpha.max(-MATE + ply as i32);
+  let beta = beta.min(MATE - ply as i32 - 1);
+  if alpha >= beta {
     return alpha;
   }
 
-  if depth > 4 {
-    return alpha;
+  let hash = board.get_hash();
+  let distance_to_leaf = max_depth - curr_depth;
+  let entry = tm.tt.probe(hash);
+  if let Some(e) = entry {
+    let score = score_from_tt(e.score, ply);
+    if e.depth as i32 >= distance_to_leaf {
+      match e.bound {
+        tt::Bound::Exact                  => return score,
+        tt::Bound::Lower if score >= beta  => return score,
+        tt::Bound::Upper if score <= alpha => return score,
+        _                                 => {},
+      }
+    }
+  }
+
+  // tablebase results are exact, so they cut off just like a tt hit.
+  if let Some(wdl) = tm.tb.as_ref().and_then(|tb| tb.probe_wdl(&board)) {
+    tm.tbhits.fetch_add(1, Ordering::Relaxed);
+    let (score, bound) = match wdl {
+      shakmaty_syzygy::Wdl::Win         => (TB_WIN - ply as i32, tt::Bound::Lower),
+      shakmaty_syzygy::Wdl::Loss        => (-TB_WIN + ply as i32, tt::Bound::Upper),
+      shakmaty_syzygy::Wdl::CursedWin   => (1, tt::Bound::Exact),
+      shakmaty_syzygy::Wdl::BlessedLoss => (-1, tt::Bound::Exact),
+      shakmaty_syzygy::Wdl::Draw        => (0, tt::Bound::Exact),
+    };
+    if bound == tt::Bound::Exact || (bound == tt::Bound::Lower && score >= beta) || (bound == tt::Bound::Upper && score <= alpha) {
+      let eval = eval::evaluate(&board, &mut tm.pawns, &tm.weights) * color as i32;
+      tm.tt.store(hash, None, score_to_tt(score, ply), eval, (distance_to_leaf + 6).min(MAX_DEPTH as i32) as i16, bound);
+      return score;
+    }
   }
 
-  let mut value: i32 = -10000;
-  let mut iterable: chess::MoveGen = chess::MoveGen::new_legal(&board);
-  let captures: &chess::BitBoard = board.color_combined(!board.side_to_move());
-  iterable.set_iterator_mask(*captures);
-  for m in &mut iterable {
+  if curr_depth >= max_depth {
+    return quiesce(tm, board, alpha, beta, color, ply, true);
+  }
+  let in_check = board.checkers().popcnt() > 0;
+  let previous = previous_moves(tm, ply);
+  let mut picker = movepick::MovePicker::new(board, entry.and_then(|e| e.best), &tm.history, ply, previous);
+  // mate and stalemate come before any pruning, which would trust the static eval instead.
+  if picker.legal_moves() == 0 {
+    return if in_check {-MATE + ply as i32} else {0};
+  }
+  let eval = match entry {
+    Some(e) => e.eval,
+    None    => eval::evaluate(&board, &mut tm.pawns, &tm.weights) * color as i32,
+  };
+  tm.evals[ply] = if in_check {None} else {Some(eval)};
+  let improving = is_improving(&tm.evals, ply);
+  // nothing is pruned in check, where the eval says little and mate has to be found.
+  if !in_check && curr_depth < 4 && eval - 225 * curr_depth + 100 * improving as i32 >= beta {
+    // Reverse Futility Pruning
+    tm.stats.rfp += 1;
+    return eval;
+  }
+  if curr_depth > 1 && !in_check && distance_to_leaf < 4 && eval + 300*distance_to_leaf < beta {
+    // Futility Pruning
+    tm.stats.futility += 1;
+    return eval;
+  }
+  if null_move_allowed(tm, &board, ply, distance_to_leaf, eval, beta) {
+    // Null Move Pruning, a position that holds even after passing needs no full search.
+    let r = 3 + distance_to_leaf / 3 + ((eval - beta) / 200).min(3);
+    let null_board = board.null_move().unwrap();
+    tm.played[ply] = None;
+    let score = -alpha_beta(tm, null_board, curr_depth + 1, max_depth - r, -beta, -beta + 1, -color);
+    if tm.should_stop.load(Ordering::Relaxed) {
+      return 0;
+    }
+    if score >= beta {
+      // a mate found after passing proves nothing.
+      let score = if score >= TB_WIN - MAX_PLY as i32 {beta} else {score};
+      if tm.nmp_min_ply != 0 || distance_to_leaf < NMP_VERIFY_DEPTH {
+        tm.stats.nmp += 1;
+        return score;
+      }
+      // at high depth, zugzwang is ruled out by a reduced search without null moves of our own.
+      tm.nmp_min_ply = ply + 3 * (distance_to_leaf - r) as usize / 4;
+      let verified = alpha_beta(tm, board, curr_depth, max_depth - r, beta - 1, beta, color);
+      tm.nmp_min_ply = 0;
+      if tm.should_stop.load(Ordering::Relaxed) {
+        return 0;
+      }
+      if verified >= beta {
+        tm.stats.nmp += 1;
+        return score;
+      }
+    }
+  }
+  let original_alpha = alpha;
+  let mut best: Option<chess::ChessMove> = None;
+  let mut value: i32 = -INFINITE;
+  let pv_node = beta - alpha > 1;
+  let mut quiets: Vec<history::PieceMove> = vec![];
+  let mut searched = 0;
+  while let Some(m) = picker.next(&tm.history) {
+    // bad captures come late in the ordering, once a move is searched they rarely matter.
+    if searched > 0 && !in_check && distance_to_leaf <= SEE_PRUNE_DEPTH && value > -MATE_BOUND
+      && see::is_capture(&board, m) && !see::see(&board, m, -SEE_PRUNE_MARGIN * distance_to_leaf) {
+      continue;
+    }
+    let quiet = !see::is_capture(&board, m) && m.get_promotion().is_none();
+    if quiet && !pv_node && !in_check && distance_to_leaf <= LMP_DEPTH && value > -MATE_BOUND && searched >= lmp_count(distance_to_leaf, improving) {
+      // Late Move Pruning
+      tm.stats.lmp += 1;
+      continue;
+    }
+    searched += 1;
+    let piece_move = history::PieceMove::new(&board, m);
+    if quiet {
+      quiets.push(piece_move);
+    }
     let mut result: chess::Board = board.clone();
     board.make_move(m, &mut result);
-    let r: i32 = -quiesce(board, -beta, -alpha, -color, depth + 1);
+    tm.played[ply] = Some(piece_move);
+    let gives_check = result.checkers().popcnt() > 0;
+    let mut reduction = 0;
+    if quiet && !in_check && distance_to_leaf >= LMR_DEPTH && searched > LMR_MOVES {
+      // Late Move Reductions, less for moves that look promising and more when the position isn't improving.
+      let killer = tm.history.is_killer(ply, m);
+      reduction = reduction_table()[(distance_to_leaf as usize).min(63)][searched.min(63)]
+        - pv_node as i32 - killer as i32 - gives_check as i32 + !improving as i32
+        - tm.history.score(board.side_to_move(), piece_move, &previous) / LMR_HISTORY_DIVISOR;
+      reduction = reduction.clamp(0, distance_to_leaf - 2);
+    }
+    // Principal Variation Search, only the first move gets the full window.
+    let mut r: i32 = if searched == 1 {-INFINITE} else {-alpha_beta(tm, result, curr_depth + 1, max_depth - reduction, -alpha - 1, -alpha, -color)};
+    if reduction > 0 && r > alpha && !tm.should_stop.load(Ordering::Relaxed) {
+      // the reduced search failed high, the move gets the full depth after all.
+      r = -alpha_beta(tm, result, curr_depth + 1, max_depth, -alpha - 1, -alpha, -color);
+    }
+    if (searched == 1 || (pv_node && r > alpha && r < beta)) && !tm.should_stop.load(Ordering::Relaxed) {
+      r = -alpha_beta(tm, result, curr_depth + 1, max_depth, -beta, -alpha, -color);
+    }
+    if tm.should_stop.load(Ordering::Relaxed) {
+      return 0;
+    }
     if r > value {
       value = r;
     }
     if value > alpha {
       alpha = value;
+      best = Some(m);
+      tm.update_pv(ply, m);
     }
     if r >= beta {
-      return value;
+      if quiet {
+        tm.history.update(board.side_to_move(), ply, distance_to_leaf, piece_move, &quiets, &previous);
+      }
+      tm.tt.store(hash, Some(m), score_to_tt(beta, ply), eval, distance_to_leaf as i16, tt::Bound::Lower);
+      return beta;
     }
   }
+  let bound = if alpha > original_alpha {tt::Bound::Exact} else {tt::Bound::Upper};
+  tm.tt.store(hash, best, score_to_tt(value, ply), eval, distance_to_leaf as i16, bound);
   return value;
 }
 
-fn score_killers(tm: *mut ThreadManager, board: chess::Board) -> Vec<i32> {
-  let mut scores: Vec<i32> = vec![];
-  let mut iterable = chess::MoveGen::new_legal(&board);
-  iterable.set_iterator_mask(!chess::EMPTY);
-  for m in &mut iterable {
-    let mut result: chess::Board = board.clone();
-    board.make_move(m, &mut result);
-    if unsafe{(*tm).history.killers[m.get_source().to_index()].len() > m.get_dest().to_index()} {
-      if unsafe{(*tm).history.killers[m.get_source().to_index()][m.get_dest().to_index()] == m} {
-        scores.push(10);
-      } else {
-        scores.push(0);
-      }
-    } else {
-      scores.push(0);
+// whether the static eval went up since our own previous move, two plies back.
+fn is_improving(evals: &[Option<i32>], ply: usize) -> bool {
+  if ply < 2 {
+    return false;
+  }
+  return match (evals[ply], evals[ply - 2]) {
+    (Some(now), Some(before)) => now > before,
+    _                         => false,
+  };
+}
+
+// reductions grow with the log of both the depth left and the number of moves already searched.
+fn build_reductions() -> [[i32; 64]; 64] {
+  let mut table = [[0; 64]; 64];
+  for (depth, row) in table.iter_mut().enumerate().skip(1) {
+    for (moves, r) in row.iter_mut().enumerate().skip(1) {
+      *r = (0.75 + (depth as f64).ln() * (moves as f64).ln() / 2.25) as i32;
     }
   }
-  return scores;
+  return table;
 }
 
-fn score_counters(tm: *mut ThreadManager, board: chess::Board) -> Vec<i32> {
-  let mut iterable = chess::MoveGen::new_legal(&board);
-  iterable.set_iterator_mask(!chess::EMPTY);
-  let mut scores = vec![];
-  for m in iterable {
-    scores.push(unsafe{(*tm).history.counter_moves[m.get_source().to_index()][m.get_dest().to_index()]});
+fn reduction_table() -> &'static [[i32; 64]; 64] {
+  static TABLE: OnceLock<[[i32; 64]; 64]> = OnceLock::new();
+  return TABLE.get_or_init(build_reductions);
+}
+
+// how many quiet moves are worth trying this close to the leaves.
+fn lmp_count(depth: i32, improving: bool) -> usize {
+  return ((3 + depth * depth) / (2 - improving as i32)) as usize;
+}
+
+// the opponent's last move and our own move before it, for the counter-move and continuation tables.
+fn previous_moves(tm: &ThreadManager, ply: usize) -> [Option<history::PieceMove>; 2] {
+  return [
+    if ply >= 1 {tm.played[ply - 1]} else {None},
+    if ply >= 2 {tm.played[ply - 2]} else {None},
+  ];
+}
+
+fn null_move_allowed(tm: &ThreadManager, board: &chess::Board, ply: usize, depth: i32, eval: i32, beta: i32) -> bool {
+  if depth < NMP_DEPTH || ply < tm.nmp_min_ply || eval < beta || beta.abs() >= TB_WIN - MAX_PLY as i32 {
+    return false;
+  }
+  // never two null moves in a row, and never in check.
+  if tm.played[ply - 1].is_none() || board.checkers().popcnt() > 0 {
+    return false;
   }
-  return scores;
+  // with only pawns left, passing is often the best move there is.
+  let us = *board.color_combined(board.side_to_move());
+  let pieces = us & !*board.pieces(chess::Piece::Pawn) & !*board.pieces(chess::Piece::King);
+  return pieces.popcnt() > 0;
 }
 
-fn order(tm: *mut ThreadManager, board: chess::Board) -> std::vec::IntoIter<chess::ChessMove> {
-  // sum all scores and then order with it.
-  let mut scores = vec![];
-  let ks = score_killers(tm, board);
-  let cs = score_counters(tm, board);
-  for i in 0..cs.len() {
-    scores.push(cs[i] + ks[i]);
+// counts the node and raises the shared stop flag once a node or time limit is hit.
+fn should_stop(tm: &ThreadManager) -> bool {
+  let nodes = tm.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+  let out_of_nodes = tm.is_main() && tm.stopper.nodes.is_some_and(|n| tm.total_nodes() >= n);
+  let out_of_time = nodes.is_multiple_of(CHECK_INTERVAL) && tm.stopper.time.as_ref().is_some_and(|t| t.out_of_time(tm.stopper.elapsed()));
+  if out_of_nodes || out_of_time {
+    tm.should_stop.store(true, Ordering::Relaxed);
   }
-  let mut iterable = chess::MoveGen::new_legal(&board);
-  iterable.set_iterator_mask(!chess::EMPTY);
-  let mut moves: Vec<chess::ChessMove> = [].to_vec();
-  for i in iterable {
-    moves.push(i)
+  return tm.should_stop.load(Ordering::Relaxed);
+}
+
+// captures and queen promotions only, or every evasion when in check.
+// counted is set when alpha_beta hands over its leaf, which it already added to the node count.
+fn quiesce(tm: &mut ThreadManager, board: chess::Board, mut alpha: i32, beta: i32, color: i8, ply: usize, counted: bool) -> i32 {
+  let stop = if counted {tm.should_stop.load(Ordering::Relaxed)} else {should_stop(tm)};
+  if stop {
+    return 0;
+  }
+  tm.stats.qnodes += 1;
+  tm.seldepth = tm.seldepth.max(ply);
+  let in_check = board.checkers().popcnt() > 0;
+  // the generator counts every legal move before its mask narrows it down to the captures.
+  let iterable = chess::MoveGen::new_legal(&board);
+  if iterable.len() == 0 {
+    return if in_check {-MATE + ply as i32} else {0};
+  }
+  let stand_pat = if in_check {-INFINITE} else {eval::evaluate(&board, &mut tm.pawns, &tm.weights) * color as i32};
+  if ply >= MAX_PLY - 1 {
+    return if in_check {0} else {stand_pat};
+  }
+  if stand_pat >= beta {
+    return beta;
+  }
+  if alpha < stand_pat {
+    alpha = stand_pat;
   }
 
-  for i in 0..scores.len() {
-    if let Some((j, _)) = scores.iter()
-                              .enumerate()
-                              .skip(i)
-                              .min_by_key(|x| x.1) {
-      scores.swap(i, j);
-      moves.swap(i, j);
+  let moves = qsearch_moves(&board, iterable, in_check);
+  for (m, gain) in moves {
+    if !in_check {
+      // even winning the piece outright with some margin to spare would not reach alpha.
+      if stand_pat + gain + DELTA_MARGIN <= alpha {
+        tm.stats.delta += 1;
+        continue;
+      }
+      if !see::see(&board, m, 0) {
+        continue;
+      }
+    }
+    let result = board.make_move_new(m);
+    let r: i32 = -quiesce(tm, result, -beta, -alpha, -color, ply + 1, false);
+    if tm.should_stop.load(Ordering::Relaxed) {
+      return 0;
+    }
+    if r >= beta {
+      return beta;
+    }
+    if r > alpha {
+      alpha = r;
     }
   }
-  return moves.into_iter();
+  return alpha;
 }
 
-
-fn eval(board: chess::Board) -> i32 {
-  let s: chess::BoardStatus = board.status();
-  if !(s == chess::BoardStatus::Ongoing) {
-    if s == chess::BoardStatus::Checkmate {
-      return -10000;
+fn qsearch_moves(board: &chess::Board, mut iterable: chess::MoveGen, in_check: bool) -> Vec<(chess::ChessMove, i32)> {
+  let mut moves: Vec<(chess::ChessMove, i32, i32)> = vec![];
+  if !in_check {
+    let mut targets = *board.color_combined(!board.side_to_move());
+    if let Some(sq) = board.en_passant().and_then(|sq| sq.forward(board.side_to_move())) {
+      targets |= chess::BitBoard::from_square(sq);
     }
-    return 0;
+    let promotions = !*board.combined() & chess::get_rank(board.side_to_move().to_their_backrank());
+    iterable.set_iterator_mask(targets | promotions);
+  }
+  for m in iterable {
+    // underpromotions are left to the main search.
+    if !in_check && m.get_promotion().is_some_and(|p| p != chess::Piece::Queen) {
+      continue;
+    }
+    if !in_check && board.piece_on(m.get_dest()).is_none() && m.get_promotion().is_none() && board.piece_on(m.get_source()) != Some(chess::Piece::Pawn) {
+      continue;
+    }
+    moves.push((m, see::gain(board, m), movepick::mvv_lva(board, m)));
   }
+  moves.sort_by_key(|m| -m.2);
+  return moves.into_iter().map(|m| (m.0, m.1)).collect();
+}
 
-  // material
-  let mut material: i32 = 0;
-  let b: String = board.to_string();
-  let mut count = 0;
-  for i in b.chars() {
-    count += 1;
-    match i {
-      'P' => material += 100 * ((if count <= 4 {count / 4} else {4/count}) as i32).abs(),
-      'R' => material += 500,
-      'N' => material += 320,
-      'B' => material += 340,
-      'Q' => material += 900,
-
-      'p' => material -= 100 * ((if count <= 3 {count / 3} else {3/count}) as i32).abs(),
-      'r' => material -= 500,
-      'n' => material -= 320,
-      'b' => material -= 340,
-      'q' => material -= 900,
-      '/' => count += 1,
-      ' ' => break,
-      _   => continue,
-    }
-  }
-
-  if material > 600 || material < -600 {
-    return material;
-  }
-
-  let eval_board = board.clone();
-  // mobility
-  let currmobility = chess::MoveGen::new_legal(&eval_board).len() as i32;
-  eval_board.null_move();
-  let theirmobility = chess::MoveGen::new_legal(&eval_board).len() as i32;
-  eval_board.null_move();
-  let mobility_score: i32 = currmobility - theirmobility;
-
-  // castling
-  let mut castling_score: i32 = 0;
-  if board.castle_rights(chess::Color::White) == chess::CastleRights::Both {
-    castling_score += 10;
-  } else if board.castle_rights(chess::Color::White) == chess::CastleRights::KingSide || board.castle_rights(chess::Color::White) == chess::CastleRights::QueenSide {
-    castling_score += 5;
-  }
-
-  if board.castle_rights(chess::Color::Black) == chess::CastleRights::Both {
-    castling_score -= 10;
-  } else if board.castle_rights(chess::Color::Black) == chess::CastleRights::KingSide || board.castle_rights(chess::Color::Black) == chess::CastleRights::QueenSide {
-    castling_score -= 5;
-  }
-  return material + mobility_score + castling_score;
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn improving() {
+    // our evals sit on every other ply, the opponent's in between must not count.
+    let evals = [Some(10), Some(-500), Some(30), Some(-600), Some(20), None, None];
+    assert!(!is_improving(&evals, 0));
+    assert!(!is_improving(&evals, 1));
+    assert!(is_improving(&evals, 2));
+    assert!(!is_improving(&evals, 3));
+    assert!(!is_improving(&evals, 4));
+    // in check on either ply says nothing.
+    assert!(!is_improving(&evals, 5));
+    assert!(!is_improving(&evals, 6));
+  }
+  #[test]
+  fn tt_scores() {
+    for score in [MATE - 3, -MATE + 4, TB_WIN - 7, -TB_WIN + 2, 150, -150, 0].iter() {
+      for ply in [0, 1, 5, 40].iter() {
+        assert_eq!(score_from_tt(score_to_tt(*score, *ply), *ply), *score);
+      }
+    }
+    // a mate or tablebase win found deeper in the tree keeps its distance from the node it was stored at.
+    assert_eq!(score_from_tt(score_to_tt(MATE - 7, 5), 3), MATE - 5);
+    assert_eq!(score_from_tt(score_to_tt(-MATE + 6, 4), 2), -MATE + 4);
+    assert_eq!(score_from_tt(score_to_tt(TB_WIN - 10, 10), 3), TB_WIN - 3);
+    assert_eq!(score_from_tt(score_to_tt(-TB_WIN + 10, 10), 3), -TB_WIN + 3);
+    assert_eq!(score_from_tt(score_to_tt(150, 10), 3), 150);
+  }
+}
@@ -0,0 +1,147 @@
+extern crate chess;
+extern crate shakmaty;
+extern crate shakmaty_syzygy;
+use self::shakmaty::{Bitboard, Board, ByColor, ByRole, CastlingMode, Chess, Color, FromSetup, Setup, Square};
+use self::shakmaty_syzygy::{Tablebase, Wdl};
+
+// directories in a SyzygyPath are separated like PATH entries.
+static SEPARATOR: char = if cfg!(windows) {';'} else {':'};
+
+pub struct Tablebases {
+  tables: Tablebase<Chess>,
+}
+
+impl Tablebases {
+  pub fn new(path: &str) -> Result<Self, String> {
+    let mut tables = Tablebase::new();
+    let mut count = 0;
+    for dir in path.split(SEPARATOR).filter(|d| !d.is_empty()) {
+      count += tables.add_directory(dir).map_err(|e| format!("cannot read tablebases from {}: {}", dir, e))?;
+    }
+    if count == 0 {
+      return Err(format!("no tablebases found in {}", path));
+    }
+    return Ok(Self { tables: tables });
+  }
+
+  pub fn max_pieces(&self) -> usize {
+    return self.tables.max_pieces();
+  }
+
+  fn covers(&self, board: &chess::Board) -> bool {
+    return board.combined().popcnt() as usize <= self.max_pieces()
+      && board.castle_rights(chess::Color::White) == chess::CastleRights::NoRights
+      && board.castle_rights(chess::Color::Black) == chess::CastleRights::NoRights;
+  }
+
+  // the board does not know the fifty move counter, so probes assume it was just reset.
+  pub fn probe_wdl(&self, board: &chess::Board) -> Option<Wdl> {
+    if !self.covers(board) {
+      return None;
+    }
+    return self.tables.probe_wdl_after_zeroing(&to_position(board)?).ok();
+  }
+
+  // the root moves that keep the best tablebase result, the quickest wins or the slowest losses.
+  pub fn root_moves(&self, board: &chess::Board) -> Option<Vec<chess::ChessMove>> {
+    if !self.covers(board) {
+      return None;
+    }
+    let mut ranked = vec![];
+    for m in chess::MoveGen::new_legal(board) {
+      let result = board.make_move_new(m);
+      let rank = if chess::MoveGen::new_legal(&result).len() == 0 {
+        if result.checkers().popcnt() > 0 {(2, 0)} else {(1, 0)}
+      } else {
+        // from the opponent's point of view, in plies to the next zeroing move.
+        let dtz = self.tables.probe_dtz(&to_position(&result)?).ok()?.ignore_rounding().0;
+        match dtz {
+          d if (-100..0).contains(&d) => (2, d),
+          d if (1..101).contains(&d)  => (0, d),
+          // cursed wins and blessed losses end in a draw under the fifty move rule.
+          _                            => (1, 0),
+        }
+      };
+      ranked.push((m, rank));
+    }
+    let best = ranked.iter().map(|r| r.1).max()?;
+    return Some(ranked.into_iter().filter(|r| r.1 == best).map(|r| r.0).collect());
+  }
+}
+
+// shakmaty's view of the board, also used for the polyglot keys.
+pub fn to_position(board: &chess::Board) -> Option<Chess> {
+  let bits = |piece: chess::Piece| Bitboard(board.pieces(piece).0);
+  let by_role = ByRole {
+    pawn:   bits(chess::Piece::Pawn),
+    knight: bits(chess::Piece::Knight),
+    bishop: bits(chess::Piece::Bishop),
+    rook:   bits(chess::Piece::Rook),
+    queen:  bits(chess::Piece::Queen),
+    king:   bits(chess::Piece::King),
+  };
+  let by_color = ByColor {
+    black: Bitboard(board.color_combined(chess::Color::Black).0),
+    white: Bitboard(board.color_combined(chess::Color::White).0),
+  };
+  let mut setup = Setup::empty();
+  setup.board = Board::from_bitboards(by_role, by_color);
+  setup.turn = if board.side_to_move() == chess::Color::White {Color::White} else {Color::Black};
+  // shakmaty keeps the castling rights as the squares of the rooks that may still castle.
+  for (color, rank) in [(chess::Color::White, 0), (chess::Color::Black, 7)].iter() {
+    let rights = board.castle_rights(*color);
+    if rights.has_kingside() {
+      setup.castling_rights.add(Square::new(rank * 8 + 7));
+    }
+    if rights.has_queenside() {
+      setup.castling_rights.add(Square::new(rank * 8));
+    }
+  }
+  // the chess crate keeps the square of the pawn that can be taken en passant instead of the one behind it.
+  setup.ep_square = board.en_passant().and_then(|sq| sq.forward(board.side_to_move())).map(|sq| Square::new(sq.to_index() as u32));
+  return Chess::from_setup(setup, CastlingMode::Standard).ok();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::shakmaty::fen::Fen;
+  use super::shakmaty::EnPassantMode;
+  use std::str::FromStr;
+
+  #[test]
+  fn positions() {
+    let fens = [
+      "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+      "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+      "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 1",
+      "r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1",
+      "r3k2r/8/8/8/8/8/8/R3K2R b Qk - 0 1",
+      "8/8/3k4/8/8/3K4/8/7R b - - 0 1",
+    ];
+    for fen in fens.iter() {
+      let board = chess::Board::from_str(fen).unwrap();
+      let position = to_position(&board).unwrap();
+      assert_eq!(Fen::from_position(position, EnPassantMode::Legal).to_string(), *fen);
+    }
+  }
+
+  // needs the 3-4-5 piece tables in SYZYGY_PATH, otherwise there is nothing to probe.
+  #[test]
+  fn probes() {
+    let tables = match std::env::var("SYZYGY_PATH").ok().and_then(|path| Tablebases::new(&path).ok()) {
+      Some(tables) => tables,
+      None         => return,
+    };
+    let won = chess::Board::from_str("8/8/3k4/8/8/3K4/8/7R w - - 0 1").unwrap();
+    assert_eq!(tables.probe_wdl(&won), Some(Wdl::Win));
+    let lost = chess::Board::from_str("8/8/3k4/8/8/3K4/8/7R b - - 0 1").unwrap();
+    assert_eq!(tables.probe_wdl(&lost), Some(Wdl::Loss));
+    let drawn = chess::Board::from_str("8/8/3k4/8/8/3K4/8/8 w - - 0 1").unwrap();
+    assert_eq!(tables.probe_wdl(&drawn), Some(Wdl::Draw));
+    // every move kept at the root must keep the win.
+    let moves = tables.root_moves(&won).unwrap();
+    assert!(!moves.is_empty());
+    assert!(moves.iter().all(|m| tables.probe_wdl(&won.make_move_new(*m)) == Some(Wdl::Loss)));
+  }
+}
@@ -0,0 +1,126 @@
+extern crate chess;
+use uci;
+
+// time kept in reserve for the gui and for communication lag.
+static MOVE_OVERHEAD: i64 = 30;
+// moves we plan for when the gui does not send movestogo.
+static DEFAULT_MOVESTOGO: i64 = 30;
+// how much the soft limit is stretched when the best move keeps changing, indexed by stability.
+static STABILITY_SCALE: [f64; 5] = [2.0, 1.3, 1.0, 0.85, 0.75];
+
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+  pub soft:   u128,
+  pub hard:   u128,
+  stability:  usize,
+  best:       Option<chess::ChessMove>,
+  score:      Option<i32>,
+  score_drop: i32,
+  // movetime asks for the whole budget to be used.
+  fixed:      bool,
+}
+
+impl TimeManager {
+  // returns None when the search is not bound by the clock.
+  pub fn new(params: &uci::GoParams, side: chess::Color) -> Option<Self> {
+    if params.infinite {
+      return None;
+    }
+    if let Some(movetime) = params.movetime {
+      let budget = (movetime - MOVE_OVERHEAD).max(1) as u128;
+      let mut tm = Self::with_limits(budget, budget);
+      tm.fixed = true;
+      return Some(tm);
+    }
+
+    let (clock, inc) = if side == chess::Color::White {(params.wtime, params.winc)} else {(params.btime, params.binc)};
+    let clock = clock?;
+    let inc = inc.unwrap_or(0).max(0);
+    let available = (clock - MOVE_OVERHEAD).max(1);
+    let movestogo = params.movestogo.map(|m| (m as i64).clamp(1, 50)).unwrap_or(DEFAULT_MOVESTOGO);
+
+    let optimum = available / movestogo + inc * 3 / 4;
+    // never plan on spending more than what is left, and keep some for the next moves when possible.
+    let hard = (optimum * 4).min(if movestogo == 1 {available} else {available * 4 / 5}).max(1);
+    let soft = optimum.min(hard).max(1);
+    return Some(Self::with_limits(soft as u128, hard as u128));
+  }
+
+  fn with_limits(soft: u128, hard: u128) -> Self {
+    return Self { soft: soft, hard: hard, stability: 0, best: None, score: None, score_drop: 0, fixed: false };
+  }
+
+  // called after every completed iteration with its best move and score.
+  pub fn update(&mut self, best: chess::ChessMove, score: i32) {
+    if self.best == Some(best) {
+      self.stability = (self.stability + 1).min(STABILITY_SCALE.len() - 1);
+    } else {
+      self.stability = 0;
+    }
+    self.score_drop = match self.score {
+      Some(previous) => previous - score,
+      None           => 0,
+    };
+    self.best = Some(best);
+    self.score = Some(score);
+  }
+
+  // the soft limit scaled by how settled the search looks.
+  pub fn optimum(&self) -> u128 {
+    let swing = (1.0 + self.score_drop as f64 / 200.0).clamp(0.8, 1.6);
+    let scaled = self.soft as f64 * STABILITY_SCALE[self.stability] * swing;
+    return (scaled as u128).min(self.hard);
+  }
+
+  // whether another iteration should be started.
+  pub fn should_continue(&self, elapsed: u128) -> bool {
+    if self.fixed {
+      return elapsed < self.hard;
+    }
+    // an iteration rarely takes less than the previous ones together, so don't start one that can't finish.
+    return elapsed < self.optimum() / 2 && elapsed < self.hard;
+  }
+
+  pub fn out_of_time(&self, elapsed: u128) -> bool {
+    return elapsed >= self.hard;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn last_move_before_the_control() {
+    let params = uci::GoParams { wtime: Some(10000), movestogo: Some(1), ..Default::default() };
+    let tm = TimeManager::new(&params, chess::Color::White).unwrap();
+    // the whole clock may go into the move, but never the overhead.
+    assert_eq!(tm.hard, (10000 - MOVE_OVERHEAD) as u128);
+    assert!(tm.soft <= tm.hard);
+    assert!(tm.should_continue(0));
+    assert!(!tm.should_continue(tm.hard));
+    assert!(tm.out_of_time(tm.hard));
+  }
+
+  #[test]
+  fn increment_larger_than_clock() {
+    let params = uci::GoParams { btime: Some(100), binc: Some(5000), wtime: Some(60000), ..Default::default() };
+    let tm = TimeManager::new(&params, chess::Color::Black).unwrap();
+    // the increment only arrives after the move, the budget has to fit in what is on the clock.
+    assert!(tm.hard < 100 - MOVE_OVERHEAD as u128);
+    assert!(tm.soft <= tm.hard);
+    assert!(tm.optimum() <= tm.hard);
+  }
+
+  #[test]
+  fn movetime() {
+    let params = uci::GoParams { movetime: Some(1000), wtime: Some(5), ..Default::default() };
+    let tm = TimeManager::new(&params, chess::Color::White).unwrap();
+    assert_eq!(tm.soft, tm.hard);
+    assert_eq!(tm.hard, (1000 - MOVE_OVERHEAD) as u128);
+    // the whole budget gets used, whatever the stability.
+    assert!(tm.should_continue(tm.hard - 1));
+    assert!(!tm.should_continue(tm.hard));
+    assert!(TimeManager::new(&uci::GoParams { infinite: true, ..Default::default() }, chess::Color::White).is_none());
+  }
+}
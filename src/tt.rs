@@ -0,0 +1,237 @@
+extern crate chess;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+pub static DEFAULT_HASH_MB: usize = 16;
+pub static MAX_HASH_MB: usize = 32768;
+static BUCKET_SIZE: usize = 4;
+// ages are stored in 6 bits.
+static AGE_MASK: u8 = 0x3f;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bound {
+  Exact,
+  Lower,
+  Upper,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TtEntry {
+  pub best:  Option<chess::ChessMove>,
+  pub score: i32,
+  pub eval:  i32,
+  pub depth: i16,
+  pub bound: Bound,
+  pub age:   u8,
+}
+
+// the key is stored xored with the data, so a torn write from another thread just fails verification.
+#[derive(Default)]
+struct Slot {
+  key:  AtomicU64,
+  data: AtomicU64,
+}
+
+#[repr(align(64))]
+#[derive(Default)]
+struct Bucket {
+  slots: [Slot; BUCKET_SIZE],
+}
+
+pub struct TranspositionTable {
+  buckets: Vec<Bucket>,
+  age:     AtomicU8,
+}
+
+impl TranspositionTable {
+  pub fn new(mb: usize) -> Self {
+    let count = (mb.clamp(1, MAX_HASH_MB) * 1024 * 1024 / std::mem::size_of::<Bucket>()).max(1);
+    let mut buckets = Vec::with_capacity(count);
+    buckets.resize_with(count, Bucket::default);
+    return Self { buckets: buckets, age: AtomicU8::new(0) };
+  }
+
+  pub fn clear(&self) {
+    for bucket in self.buckets.iter() {
+      for slot in bucket.slots.iter() {
+        slot.key.store(0, Ordering::Relaxed);
+        slot.data.store(0, Ordering::Relaxed);
+      }
+    }
+    self.age.store(0, Ordering::Relaxed);
+  }
+
+  // called once per search so that entries from older searches get replaced first.
+  pub fn new_search(&self) {
+    let age = (self.age.load(Ordering::Relaxed) + 1) & AGE_MASK;
+    self.age.store(age, Ordering::Relaxed);
+  }
+
+  fn bucket(&self, hash: u64) -> &Bucket {
+    let index = ((hash as u128 * self.buckets.len() as u128) >> 64) as usize;
+    return &self.buckets[index];
+  }
+
+  pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+    for slot in self.bucket(hash).slots.iter() {
+      let data = slot.data.load(Ordering::Relaxed);
+      if data != 0 && slot.key.load(Ordering::Relaxed) ^ data == hash {
+        return Some(unpack(data));
+      }
+    }
+    return None;
+  }
+
+  pub fn store(&self, hash: u64, best: Option<chess::ChessMove>, score: i32, eval: i32, depth: i16, bound: Bound) {
+    let age = self.age.load(Ordering::Relaxed);
+    let bucket = self.bucket(hash);
+
+    // reuse the slot of the same position, otherwise replace the shallowest and oldest one.
+    let mut replace = &bucket.slots[0];
+    let mut replace_value = i32::MAX;
+    for slot in bucket.slots.iter() {
+      let data = slot.data.load(Ordering::Relaxed);
+      if data == 0 || slot.key.load(Ordering::Relaxed) ^ data == hash {
+        replace = slot;
+        break;
+      }
+      let entry = unpack(data);
+      let value = entry.depth as i32 - 8 * (age.wrapping_sub(entry.age) & AGE_MASK) as i32;
+      if value < replace_value {
+        replace = slot;
+        replace_value = value;
+      }
+    }
+
+    let mut best = best;
+    let old = replace.data.load(Ordering::Relaxed);
+    if best.is_none() && old != 0 && replace.key.load(Ordering::Relaxed) ^ old == hash {
+      // keep the move we already knew for this position.
+      best = unpack(old).best;
+    }
+    let data = pack(TtEntry { best: best, score: score, eval: eval, depth: depth, bound: bound, age: age });
+    replace.key.store(hash ^ data, Ordering::Relaxed);
+    replace.data.store(data, Ordering::Relaxed);
+  }
+
+  // permill of the sampled entries written during the current search.
+  pub fn hashfull(&self) -> usize {
+    let age = self.age.load(Ordering::Relaxed);
+    let sample = self.buckets.len().min(1000 / BUCKET_SIZE);
+    let mut used = 0;
+    for bucket in self.buckets[..sample].iter() {
+      for slot in bucket.slots.iter() {
+        let data = slot.data.load(Ordering::Relaxed);
+        if data != 0 && unpack(data).age == age {
+          used += 1;
+        }
+      }
+    }
+    return used * 1000 / (sample * BUCKET_SIZE);
+  }
+}
+
+// layout: move 16 bits | score 16 | eval 16 | depth 8 | bound 2 | age 6
+fn pack(e: TtEntry) -> u64 {
+  let bound: u64 = match e.bound {
+    Bound::Exact => 1,
+    Bound::Lower => 2,
+    Bound::Upper => 3,
+  };
+  return encode_move(e.best) as u64
+    | ((e.score as i16 as u16 as u64) << 16)
+    | ((e.eval as i16 as u16 as u64) << 32)
+    | ((e.depth.clamp(0, 255) as u64) << 48)
+    | (bound << 56)
+    | (((e.age & AGE_MASK) as u64) << 58);
+}
+
+fn unpack(data: u64) -> TtEntry {
+  let bound = match (data >> 56) & 3 {
+    1 => Bound::Exact,
+    2 => Bound::Lower,
+    _ => Bound::Upper,
+  };
+  return TtEntry {
+    best:  decode_move(data as u16),
+    score: (data >> 16) as u16 as i16 as i32,
+    eval:  (data >> 32) as u16 as i16 as i32,
+    depth: ((data >> 48) & 0xff) as i16,
+    bound: bound,
+    age:   ((data >> 58) as u8) & AGE_MASK,
+  };
+}
+
+// from 6 bits | to 6 bits | promotion 3 bits, 0 meaning no move.
+fn encode_move(m: Option<chess::ChessMove>) -> u16 {
+  return match m {
+    None    => 0,
+    Some(m) => {
+      let promotion: u16 = match m.get_promotion() {
+        Some(chess::Piece::Knight) => 1,
+        Some(chess::Piece::Bishop) => 2,
+        Some(chess::Piece::Rook)   => 3,
+        Some(chess::Piece::Queen)  => 4,
+        _                          => 0,
+      };
+      m.get_source().to_index() as u16 | ((m.get_dest().to_index() as u16) << 6) | (promotion << 12)
+    },
+  };
+}
+
+fn decode_move(m: u16) -> Option<chess::ChessMove> {
+  if m == 0 {
+    return None;
+  }
+  let promotion = match m >> 12 {
+    1 => Some(chess::Piece::Knight),
+    2 => Some(chess::Piece::Bishop),
+    3 => Some(chess::Piece::Rook),
+    4 => Some(chess::Piece::Queen),
+    _ => None,
+  };
+  return Some(chess::ChessMove::new(chess::ALL_SQUARES[(m & 0x3f) as usize], chess::ALL_SQUARES[((m >> 6) & 0x3f) as usize], promotion));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  #[test]
+  fn store_and_probe() {
+    let tt = TranspositionTable::new(1);
+    let promotion = chess::ChessMove::from_str("b7a8q").unwrap();
+    let quiet = chess::ChessMove::from_str("g1f3").unwrap();
+    let entries = [
+      (0x0123_4567_89ab_cdef, Some(promotion), 31990, -250, 12, Bound::Exact),
+      (0xfedc_ba98_7654_3210, Some(quiet), -31990, 17, 0, Bound::Lower),
+      (0x0f0f_0f0f_f0f0_f0f0, None, -5, -32000, 99, Bound::Upper),
+    ];
+    for (hash, best, score, eval, depth, bound) in entries.iter() {
+      tt.store(*hash, *best, *score, *eval, *depth, *bound);
+    }
+    for (hash, best, score, eval, depth, bound) in entries.iter() {
+      let entry = tt.probe(*hash).unwrap();
+      assert_eq!(entry.best, *best);
+      assert_eq!(entry.score, *score);
+      assert_eq!(entry.eval, *eval);
+      assert_eq!(entry.depth, *depth);
+      assert_eq!(entry.bound, *bound);
+    }
+    // another key in the same bucket must not be taken for a stored one.
+    assert!(tt.probe(0x0123_4567_89ab_cdee).is_none());
+    assert!(tt.probe(0).is_none());
+  }
+
+  #[test]
+  fn keeps_known_move() {
+    let tt = TranspositionTable::new(1);
+    let m = chess::ChessMove::from_str("e2e4").unwrap();
+    tt.store(42, Some(m), 30, 20, 5, Bound::Lower);
+    tt.store(42, None, -40, 20, 6, Bound::Upper);
+    let entry = tt.probe(42).unwrap();
+    assert_eq!(entry.best, Some(m));
+    assert_eq!(entry.score, -40);
+    assert_eq!(entry.depth, 6);
+  }
+}
@@ -1,48 +1,371 @@
 extern crate chess;
 use std::str::FromStr;
+use std::io::BufRead;
 use search;
-use std::time::SystemTime;
+use tt;
+use sthread;
+use perft;
+use bench;
+use eval;
+use book;
 
 pub struct UciParser {
-  alpha: i32,
-  beta : i32,
-  searcher: search::Search,
   executer: UciFunctions
 }
 
 impl UciParser {
   pub fn new() -> Self {
-    return Self { alpha: 10000, beta: -10000, searcher: search::Search::new(), executer: UciFunctions::new() }
+    return Self { executer: UciFunctions::new() }
+  }
+
+  pub fn run(&mut self) {
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+      let line = match line {
+        Ok(l)  => l,
+        Err(_) => break,
+      };
+      if !self.parse(&line) {
+        break;
+      }
+    }
+    self.executer.stop();
+  }
+
+  // returns false once the engine should quit.
+  pub fn parse(&mut self, line: &str) -> bool {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() == 0 {
+      return true;
+    }
+    match tokens[0] {
+      "uci" => {
+        println!("id name rust-engine {}", env!("CARGO_PKG_VERSION"));
+        println!("id author OfekShochat");
+        println!("option name Threads type spin default {} min 1 max 256", self.executer.searcher.thread_count());
+        println!("option name Hash type spin default {} min 1 max {}", tt::DEFAULT_HASH_MB, tt::MAX_HASH_MB);
+        println!("option name EvalFile type string default <empty>");
+        println!("option name SyzygyPath type string default <empty>");
+        println!("option name OwnBook type check default false");
+        println!("option name BookFile type string default <empty>");
+        println!("option name BookDepth type spin default {} min 1 max 100", book::DEFAULT_BOOK_DEPTH);
+        println!("option name BookBestMove type check default false");
+        println!("uciok");
+      },
+      "isready"    => println!("readyok"),
+      "ucinewgame" => {
+        self.executer.new_game();
+      },
+      "setoption"  => self.parse_setoption(&tokens[1..]),
+      "position"   => self.parse_position(&tokens[1..]),
+      "go"         => self.parse_go(&tokens[1..]),
+      "stop"       => self.executer.stop(),
+      "stats"      => self.executer.stats(),
+      "perft"      => self.executer.perft(tokens.get(1).and_then(|d| d.parse().ok()).unwrap_or(1), false),
+      "divide"     => self.executer.perft(tokens.get(1).and_then(|d| d.parse().ok()).unwrap_or(1), true),
+      "bench"      => {
+        self.executer.stop();
+        bench::run(tokens.get(1).and_then(|d| d.parse().ok()).unwrap_or(bench::DEFAULT_BENCH_DEPTH));
+      },
+      "quit"       => return false,
+      _            => println!("info string unknown command {}", tokens[0]),
+    }
+    return true;
+  }
+
+  fn parse_setoption(&mut self, tokens: &[&str]) {
+    let mut name: Vec<&str> = vec![];
+    let mut value: Vec<&str> = vec![];
+    let mut in_value = false;
+    for t in tokens {
+      match *t {
+        "name"  => continue,
+        "value" => in_value = true,
+        _       => if in_value {value.push(t)} else {name.push(t)},
+      }
+    }
+    self.executer.set_option(&name.join(" "), &value.join(" "));
+  }
+
+  fn parse_position(&mut self, tokens: &[&str]) {
+    let mut fen: Vec<&str> = vec![];
+    let mut moves: Vec<&str> = vec![];
+    let mut startpos = false;
+    let mut in_moves = false;
+    for t in tokens {
+      match *t {
+        "startpos" => startpos = true,
+        "fen"      => continue,
+        "moves"    => in_moves = true,
+        _          => if in_moves {moves.push(t)} else {fen.push(t)},
+      }
+    }
+    self.executer.position(fen.join(" "), startpos, moves.join(" "));
+  }
+
+  fn parse_go(&mut self, tokens: &[&str]) {
+    self.executer.go(GoParams::parse(tokens));
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GoParams {
+  pub wtime:       Option<i64>,
+  pub btime:       Option<i64>,
+  pub winc:        Option<i64>,
+  pub binc:        Option<i64>,
+  pub movestogo:   Option<i32>,
+  pub movetime:    Option<i64>,
+  pub depth:       Option<i16>,
+  pub nodes:       Option<u64>,
+  pub mate:        Option<i16>,
+  pub infinite:    bool,
+  pub searchmoves: Vec<chess::ChessMove>,
+}
+
+impl GoParams {
+  pub fn parse(tokens: &[&str]) -> Self {
+    let mut params = GoParams::default();
+    let mut i = 0;
+    while i < tokens.len() {
+      let value = tokens.get(i + 1).copied().unwrap_or("");
+      // a missing value must not swallow the next keyword.
+      let numeric = value.parse::<i64>().is_ok();
+      match tokens[i] {
+        "wtime"     => params.wtime = value.parse().ok(),
+        "btime"     => params.btime = value.parse().ok(),
+        "winc"      => params.winc = value.parse().ok(),
+        "binc"      => params.binc = value.parse().ok(),
+        "movestogo" => params.movestogo = value.parse().ok(),
+        "movetime"  => params.movetime = value.parse().ok(),
+        "depth"     => params.depth = value.parse().ok(),
+        "nodes"     => params.nodes = value.parse().ok(),
+        "mate"      => params.mate = value.parse().ok(),
+        "infinite"  => {
+          params.infinite = true;
+          i += 1;
+          continue;
+        },
+        "searchmoves" => {
+          // every following token that parses as a move belongs to searchmoves.
+          while let Some(m) = tokens.get(i + 1).and_then(|t| chess::ChessMove::from_str(t).ok()) {
+            params.searchmoves.push(m);
+            i += 1;
+          }
+          i += 1;
+          continue;
+        },
+        _ => {
+          i += 1;
+          continue;
+        },
+      }
+      i += if numeric {2} else {1};
+    }
+    return params;
   }
 }
 
 pub struct UciFunctions {
-  searcher: search::Search,
-  board:    chess::Board
+  searcher:   search::Search,
+  board:      chess::Board,
+  // plies played since the start of the game, for the book depth.
+  ply:        usize,
+  book:       Option<book::Book>,
+  own_book:   bool,
+  book_depth: usize,
+  book_best:  bool,
 }
 
 impl UciFunctions {
   pub fn new() -> Self {
-    return Self { searcher: search::Search::new(), board: chess::Board::default() }
+    return Self { searcher: search::Search::new(), board: chess::Board::default(), ply: 0, book: None, own_book: false, book_depth: book::DEFAULT_BOOK_DEPTH, book_best: false }
   }
 
   pub fn position(&mut self, fen: String, startpos: bool, moves: String) {
     if fen.len() != 0 {
-      self.board = chess::Board::from_str(&fen).unwrap();
+      match chess::Board::from_str(&fen) {
+        Ok(b)  => self.board = b,
+        Err(_) => {
+          println!("info string invalid fen {}", fen);
+          return;
+        },
+      }
+      let fullmove: usize = fen.split_whitespace().nth(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+      self.ply = 2 * fullmove.max(1) - 2 + (self.board.side_to_move() == chess::Color::Black) as usize;
     } else if startpos {
       self.board = chess::Board::default();
-    } if moves.len() != 0 {
+      self.ply = 0;
+    }
+    if moves.len() != 0 {
       let splitted = moves.split(" ");
       for i in splitted {
+        let m = match chess::ChessMove::from_str(i) {
+          Ok(m) if self.board.legal(m) => m,
+          _ => {
+            println!("info string illegal move {}", i);
+            return;
+          },
+        };
         let mut temp: chess::Board = self.board.clone();
-        self.board.make_move(chess::ChessMove::from_str(i).unwrap(), &mut temp);
+        self.board.make_move(m, &mut temp);
         self.board = temp;
+        self.ply += 1;
+      }
+    }
+  }
+
+  pub fn board(&self) -> chess::Board {
+    return self.board;
+  }
+
+  pub fn perft(&mut self, depth: usize, split: bool) {
+    self.searcher.stop();
+    perft::run(&self.board, depth, split);
+  }
+
+  pub fn go(&mut self, params: GoParams) {
+    // book moves are played right away, unless the gui wants to search this position.
+    if self.own_book && !params.infinite && params.searchmoves.len() == 0 && self.ply < 2 * self.book_depth {
+      let board = self.board;
+      let best = self.book_best;
+      if let Some(m) = self.book.as_mut().and_then(|b| b.pick(&board, best)) {
+        self.searcher.stop();
+        println!("info string book move {}", m);
+        println!("bestmove {}", m);
+        return;
       }
     }
+    self.searcher.search_pos(self.board, -sthread::INFINITE, sthread::INFINITE, params);
+  }
+
+  pub fn stop(&mut self) {
+    self.searcher.stop();
+  }
+
+  pub fn stats(&mut self) {
+    let stats = match self.searcher.stats() {
+      Some(s) => s,
+      None    => {
+        println!("info string search still running");
+        return;
+      },
+    };
+    let mut total = sthread::SearchStats::default();
+    for s in stats.iter() {
+      println!("info string thread {} nodes {} qnodes {} rfp {} futility {} delta {} nmp {} lmp {}", s.id, s.nodes, s.qnodes, s.rfp, s.futility, s.delta, s.nmp, s.lmp);
+      total.nodes += s.nodes;
+      total.qnodes += s.qnodes;
+      total.rfp += s.rfp;
+      total.futility += s.futility;
+      total.delta += s.delta;
+      total.nmp += s.nmp;
+      total.lmp += s.lmp;
+    }
+    println!("info string total nodes {} qnodes {} rfp {} futility {} delta {} nmp {} lmp {}", total.nodes, total.qnodes, total.rfp, total.futility, total.delta, total.nmp, total.lmp);
+  }
+
+  pub fn new_game(&mut self) {
+    self.searcher.clear();
+    self.position(String::new(), true, String::new());
+  }
+
+  pub fn set_option(&mut self, name: &str, value: &str) {
+    match name.to_lowercase().as_str() {
+      "hash" => match value.parse::<usize>() {
+        Ok(mb) => self.searcher.set_hash(mb.clamp(1, tt::MAX_HASH_MB)),
+        Err(_) => println!("info string invalid value {} for {}", value, name),
+      },
+      "threads" => match value.parse::<usize>() {
+        Ok(n)  => self.searcher.set_threads(n.clamp(1, 256)),
+        Err(_) => println!("info string invalid value {} for {}", value, name),
+      },
+      "evalfile" => {
+        if value.is_empty() || value == "<empty>" {
+          self.searcher.set_weights(eval::Weights::default());
+          return;
+        }
+        match eval::Weights::load(value) {
+          Ok(w)  => self.searcher.set_weights(w),
+          Err(e) => println!("info string {}", e),
+        }
+      },
+      "syzygypath" => {
+        let path = if value == "<empty>" {""} else {value};
+        match self.searcher.set_syzygy_path(path) {
+          Ok(0)  => {},
+          Ok(n)  => println!("info string Syzygy tablebases loaded, up to {} pieces", n),
+          Err(e) => println!("info string {}", e),
+        }
+      },
+      "ownbook" => self.own_book = value == "true",
+      "bookbestmove" => self.book_best = value == "true",
+      "bookdepth" => match value.parse::<usize>() {
+        Ok(d)  => self.book_depth = d.clamp(1, 100),
+        Err(_) => println!("info string invalid value {} for {}", value, name),
+      },
+      "bookfile" => {
+        if value.is_empty() || value == "<empty>" {
+          self.book = None;
+          return;
+        }
+        match book::Book::open(value) {
+          Ok(b)  => {
+            println!("info string loaded {} book entries", b.size());
+            self.book = Some(b);
+          },
+          Err(e) => println!("info string {}", e),
+        }
+      },
+      _ => println!("info string unknown option {}", name),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(line: &str) -> GoParams {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    return GoParams::parse(&tokens);
   }
 
-  pub fn go(&mut self, depth: i16, nodes: i32, time: i16, timemn: bool) {
-    let start = SystemTime::now();
-    self.searcher.search_pos(self.board, -10000, 10000);
+  #[test]
+  fn searchmoves() {
+    let params = parse("searchmoves e2e4 d2d4 g1f3 wtime 1000 depth 5");
+    let moves: Vec<String> = params.searchmoves.iter().map(|m| m.to_string()).collect();
+    assert_eq!(moves, ["e2e4", "d2d4", "g1f3"]);
+    assert_eq!(params.wtime, Some(1000));
+    assert_eq!(params.depth, Some(5));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn infinite() {
+    let params = parse("infinite searchmoves e2e4");
+    assert!(params.infinite);
+    assert_eq!(params.searchmoves.len(), 1);
+    assert!(!parse("depth 3").infinite);
+  }
+
+  #[test]
+  fn missing_values() {
+    let params = parse("wtime btime 1000 winc x binc 10 movestogo");
+    assert_eq!(params.wtime, None);
+    assert_eq!(params.btime, Some(1000));
+    assert_eq!(params.winc, None);
+    assert_eq!(params.binc, Some(10));
+    assert_eq!(params.movestogo, None);
+  }
+
+  #[test]
+  fn huge_mate() {
+    let params = parse("mate 20000");
+    assert_eq!(params.mate, Some(20000));
+    let stopper = sthread::Stopper::new(&params, chess::Color::White);
+    assert_eq!(stopper.depth, sthread::MAX_DEPTH);
+    // too large for the field at all, searched like a plain go.
+    assert_eq!(parse("mate 99999999").mate, None);
+    assert_eq!(sthread::Stopper::new(&parse("mate 3"), chess::Color::White).depth, 5);
+  }
+}